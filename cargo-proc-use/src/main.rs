@@ -0,0 +1,249 @@
+//! # cargo-proc-use
+//!
+//! A cargo subcommand to inspect and regenerate what proc_use generates for a package,
+//! configured from `proc_use.toml` or the `[package.metadata.proc_use]` table of
+//! `Cargo.toml`.
+//!
+//! See the [README](https://github.com/Shizcow/proc_use/tree/master/cargo-proc-use) for
+//! usage.
+
+use proc_use::{OutputKind, Spec, UseBuilder};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// The usage message.
+const USAGE: &str = "\
+Usage: cargo proc-use [--manifest-path <path>] <command>
+
+Commands:
+    expand [kind]  Print what would be generated, or only the output of one kind
+    explain        List the modules found, and why each was included or excluded
+    check          Check the in-tree files are up to date
+    generate       Regenerate the in-tree files
+    migrate <file> [--inline]
+                   Convert the #[path] mods in a file to a build.rs, or a proc_use! call";
+
+fn main() {
+    std::panic::set_hook(Box::new(|info| {
+	let payload = info.payload();
+	let msg = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+	    (Some(msg), _) => msg.to_string(),
+	    (_, Some(msg)) => msg.clone(),
+	    _ => info.to_string(),
+	};
+	eprintln!("error: {}", msg);
+    }));
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("proc-use") { // invoked as `cargo proc-use`
+	args.remove(0);
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--manifest-path") {
+	if i + 1 >= args.len() {
+	    usage();
+	}
+	let path = PathBuf::from(args.remove(i + 1));
+	args.remove(i);
+	let dir = if path.is_dir() {
+	    path.as_path()
+	} else {
+	    path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."))
+	};
+	if let Err(err) = std::env::set_current_dir(dir) {
+	    panic!("Could not enter directory '{}': {}", dir.to_string_lossy(), err);
+	}
+    }
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let code = match args.as_slice() {
+	["expand"] => expand(&load().1, None),
+	["expand", kind] => match OutputKind::from_name(kind) {
+	    Some(kind) => expand(&load().1, Some(kind)),
+	    None => panic!("Invalid output kind '{}'. Expected one of \
+			    all, mod, use, test, bench, asset or manifest.", kind),
+	},
+	["explain"] => explain(&load().1),
+	["check"] => {
+	    let (spec, builder) = load();
+	    check(&spec, &builder)
+	},
+	["generate"] => {
+	    let (spec, builder) = load();
+	    generate(&spec, &builder)
+	},
+	["migrate", file] => migrate(Path::new(file), false),
+	["migrate", file, "--inline"] => migrate(Path::new(file), true),
+	["--help"] | ["-h"] | ["help"] => {
+	    println!("{}", USAGE);
+	    0
+	},
+	_ => usage(),
+    };
+    exit(code);
+}
+
+/// Prints the usage message and exits with an error.
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+/// Reads the configuration of the package in the current directory.
+fn load() -> (Spec, UseBuilder) {
+    let spec = Spec::from_manifest_dir(Path::new("."));
+    let builder = UseBuilder::from_spec(&spec);
+    (spec, builder)
+}
+
+/// Prints what would be written to each output, or only the output of one kind.
+///
+/// # Arguments
+///
+/// * `builder` - The configured builder.
+/// * `kind` - The kind of output to print, or `None` for every output configured.
+fn expand(builder: &UseBuilder, kind: Option<OutputKind>) -> i32 {
+    match kind {
+	Some(kind) => println!("{}", builder.render(kind)),
+	None => for (kind, path) in builder.plan().outputs {
+	    println!("// {} ({})", path, kind.name());
+	    println!("{}", builder.render(kind));
+	},
+    }
+    0
+}
+
+/// Prints every module found, with why it was included or excluded.
+///
+/// # Arguments
+///
+/// * `builder` - The configured builder.
+fn explain(builder: &UseBuilder) -> i32 {
+    let cwd = std::env::current_dir().and_then(|cwd| cwd.canonicalize()).ok();
+    let plan = builder.plan();
+    for module in plan.modules.iter() {
+	let path = Path::new(&module.path);
+	let path = cwd.as_ref()
+	    .and_then(|cwd| path.strip_prefix(cwd).ok())
+	    .unwrap_or(path);
+	println!("{} ({})", module.name, path.to_string_lossy());
+	let origin = match &module.origin {
+	    Some(origin) => format!(" by {}", origin),
+	    None => String::new(),
+	};
+	match &module.excluded {
+	    Some(reason) => println!("    excluded: {}", reason),
+	    None => println!("    included{}", origin),
+	}
+	for attr in module.attrs.iter() {
+	    println!("    {}", attr);
+	}
+	for use_stmt in module.uses.iter() {
+	    println!("    {}", use_stmt);
+	}
+    }
+    for use_stmt in plan.crates.iter() {
+	println!("{}", use_stmt);
+    }
+    0
+}
+
+/// Checks every in-tree file is up to date, failing if any are stale.
+///
+/// # Arguments
+///
+/// * `spec` - The package configuration.
+/// * `builder` - The configured builder.
+fn check(spec: &Spec, builder: &UseBuilder) -> i32 {
+    if spec.in_tree.is_empty() {
+	eprintln!("error: No in-tree files are configured. Probable cause: `in_tree` is not set.");
+	return 1;
+    }
+    let mut code = 0;
+    for path in spec.in_tree.iter() {
+	if builder.in_tree_up_to_date(Path::new(path)) {
+	    println!("{}: up to date", path);
+	} else {
+	    println!("{}: out of date, run `cargo proc-use generate`", path);
+	    code = 1;
+	}
+    }
+    code
+}
+
+/// Regenerates every in-tree file, only writing those that changed.
+///
+/// # Arguments
+///
+/// * `spec` - The package configuration.
+/// * `builder` - The configured builder.
+fn generate(spec: &Spec, builder: &UseBuilder) -> i32 {
+    if spec.in_tree.is_empty() {
+	eprintln!("error: No in-tree files are configured. Probable cause: `in_tree` is not set.");
+	return 1;
+    }
+    for path in spec.in_tree.iter().map(Path::new) {
+	if builder.in_tree_up_to_date(path) {
+	    println!("{}: up to date", path.to_string_lossy());
+	    continue;
+	}
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+	    if let Err(err) = std::fs::create_dir_all(dir) {
+		panic!("Could not create directory '{}': {}", dir.to_string_lossy(), err);
+	    }
+	}
+	if let Err(err) = std::fs::write(path, builder.in_tree_contents(path)) {
+	    panic!("Could not write to file '{}': {}", path.to_string_lossy(), err);
+	}
+	println!("{}: written", path.to_string_lossy());
+    }
+    0
+}
+
+/// Prints a build.rs, or a `proc_use!` invocation, equivalent to the `#[path]` mods in a file,
+/// and reports the statements that could not be converted.
+///
+/// # Arguments
+///
+/// * `file` - The file to convert.
+/// * `inline` - Whether to print a `proc_use!` invocation instead of a build.rs.
+fn migrate(file: &Path, inline: bool) -> i32 {
+    let migration = proc_use::migrate(file);
+    let lines = migration.converted.iter()
+	.map(|(first, last)| if first == last {
+	    first.to_string()
+	} else {
+	    format!("{}-{}", first, last)
+	})
+	.collect::<Vec<_>>()
+	.join(", ");
+    let code = if migration.converted.is_empty() {
+	eprintln!("error: Nothing to convert in '{}'. Probable cause: \
+		   it has no `#[path]` mods.", file.to_string_lossy());
+	1
+    } else if inline {
+	match &migration.inline {
+	    Some(invocation) => {
+		println!("// replace lines {} of {} with:", lines, file.to_string_lossy());
+		print!("{}", invocation);
+		0
+	    },
+	    None => {
+		eprintln!("error: The mods in '{}' cannot be expressed as a proc_use! invocation. \
+			   Probable cause: try without --inline.",
+			  file.to_string_lossy());
+		1
+	    },
+	}
+    } else {
+	println!("// build.rs");
+	print!("{}", migration.build_rs());
+	println!();
+	println!("// replace lines {} of {} with:", lines, file.to_string_lossy());
+	println!("include!(concat!(env!(\"OUT_DIR\"), \"/proc_use.rs\"));");
+	0
+    };
+    for (line, reason) in migration.unconverted.iter() {
+	eprintln!("{}:{}: not converted: {}", file.to_string_lossy(), line, reason);
+    }
+    code
+}
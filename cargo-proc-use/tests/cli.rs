@@ -0,0 +1,121 @@
+//! Runs the `cargo proc-use` commands against the examples and scratch packages.
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Helper function to run the subcommand as cargo would, against a package.
+///
+/// # Arguments
+///
+/// * `manifest` - The package's `Cargo.toml`, or its directory.
+/// * `command` - The subcommand to run and its arguments, eg `["expand", "mod"]`.
+fn cargo_proc_use(manifest: &Path, command: &[&str]) -> Output {
+    match Command::new(env!("CARGO_BIN_EXE_cargo-proc-use"))
+	.arg("proc-use")
+	.arg("--manifest-path").arg(manifest)
+	.args(command)
+	.output() {
+	    Ok(output) => output,
+	    Err(err) => panic!("Could not run cargo-proc-use: {}", err),
+	}
+}
+
+#[test]
+fn check_intree_example() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/intree/Cargo.toml");
+    let output = cargo_proc_use(&manifest, &["check"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "src/generated/commands.rs: up to date\n");
+}
+
+#[test]
+fn expand_manifest_example() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/manifest/Cargo.toml");
+    let output = cargo_proc_use(&manifest, &["expand"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("// proc_use.rs (all)\n"));
+    assert!(stdout.contains("mod aliased;\n"));
+    assert!(stdout.contains("use std::fmt::Write;\n"));
+    assert!(stdout.contains("// proc_use.json (manifest)\n"));
+    assert!(stdout.contains("\"hash\": \"sha256:"));
+
+    let output = cargo_proc_use(&manifest, &["expand", "use"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+#[allow(unused_imports)]
+use bar::*;
+#[allow(unused_imports)]
+use std::fmt::Write;
+");
+}
+
+#[test]
+fn explain_manifest_example() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/manifest/Cargo.toml");
+    let output = cargo_proc_use(&manifest, &["explain"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+bar (src/util/bar.rs)
+    included by glob `src/util/*.rs`
+    use bar::*;
+aliased (external/foo.rs)
+    included by file `external/foo.rs`
+use std::fmt::Write;
+");
+}
+
+#[test]
+fn generate_then_check() {
+    let dir = std::env::temp_dir().join(format!("cargo-proc-use-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src/commands")).unwrap();
+    fs::write(dir.join("proc_use.toml"), "\
+in_tree = [\"src/generated/commands.rs\"]
+
+[[modules]]
+glob = \"src/commands/*.rs\"
+").unwrap();
+    fs::write(dir.join("src/commands/build.rs"), "pub fn run() {}\n").unwrap();
+
+    let output = cargo_proc_use(&dir, &["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = cargo_proc_use(&dir, &["generate"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "src/generated/commands.rs: written\n");
+    assert!(cargo_proc_use(&dir, &["check"]).status.success());
+
+    // a new file makes the in-tree file stale again
+    fs::write(dir.join("src/commands/clean.rs"), "pub fn run() {}\n").unwrap();
+    let output = cargo_proc_use(&dir, &["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("out of date"));
+    assert!(cargo_proc_use(&dir, &["generate"]).status.success());
+    let generated = fs::read_to_string(dir.join("src/generated/commands.rs")).unwrap();
+    assert!(generated.contains("mod build;") && generated.contains("mod clean;"));
+    assert!(cargo_proc_use(&dir, &["check"]).status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_rejects_target_filter() {
+    let dir = std::env::temp_dir().join(format!("cargo-proc-use-filter-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src/commands")).unwrap();
+    fs::write(dir.join("proc_use.toml"), "\
+target = \"filter\"
+in_tree = [\"src/generated/commands.rs\"]
+
+[[modules]]
+glob = \"src/commands/*.rs\"
+").unwrap();
+    fs::write(dir.join("src/commands/probe_debug.rs"), "pub fn run() {}\n").unwrap();
+
+    // the contents would differ between debug and release builds
+    let output = cargo_proc_use(&dir, &["check"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use `target_cfg` instead"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,10 @@
+use proc_use::{AssetKind, UseBuilder};
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    UseBuilder::new()
+	.asset_glob("assets/sql/*.sql", AssetKind::Str)
+	.write_to_file_asset(out_path.join("proc_use.rs"));
+}
@@ -0,0 +1,8 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+fn main() {
+    println!("{}", CREATE_USERS);
+    for (path, bytes) in ASSETS {
+	println!("{}: {} bytes", path, bytes.len());
+    }
+}
@@ -0,0 +1,11 @@
+use std::time::Instant;
+
+include!(concat!(env!("OUT_DIR"), "/proc_use_bench.rs"));
+
+fn main() {
+    for (name, bench) in BENCHES {
+	let start = Instant::now();
+	let result = bench();
+	println!("{}: {} in {:?}", name, result, start.elapsed());
+    }
+}
@@ -0,0 +1,9 @@
+pub fn bench() -> u64 {
+    let (mut a, mut b) = (0u64, 1u64);
+    for _ in 0..90 {
+	let next = a + b;
+	a = b;
+	b = next;
+    }
+    a
+}
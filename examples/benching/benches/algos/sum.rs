@@ -0,0 +1,3 @@
+pub fn bench() -> u64 {
+    (0..1_000_000u64).sum()
+}
@@ -0,0 +1,11 @@
+use proc_use::UseBuilder;
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    UseBuilder::new()
+	.bench_glob("benches/algos/*.rs", "bench")
+	.bench_type("fn() -> u64")
+	.write_to_file_bench(out_path.join("proc_use_bench.rs"));
+}
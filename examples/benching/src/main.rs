@@ -0,0 +1,3 @@
+fn main() {
+    println!("Run `cargo bench` to run each benchmark.");
+}
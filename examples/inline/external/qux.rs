@@ -0,0 +1,3 @@
+pub fn qux() {
+    println!("Hello from qux!");
+}
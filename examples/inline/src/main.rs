@@ -3,10 +3,19 @@ proc_use_inline::proc_use! {
     use foo::*;
     #[mod("../external/bar.rs")]
     use bar::bar;
+    mod("../external/qux.rs", as = "aliased");
+    use std::collections::HashMap;
+    mod(glob = "src/plugins/*.rs");
 }
 
 fn main() {
     println!("hi");
     foo();
     bar();
+    aliased::qux();
+    let mut counts = HashMap::new();
+    counts.insert("foo", 1);
+    println!("{:?}", counts);
+    alpha::run();
+    beta::run();
 }
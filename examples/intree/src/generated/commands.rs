@@ -0,0 +1,5 @@
+// @generated by proc_use. Do not edit; changes will be overwritten.
+#[path = "../commands/build.rs"]
+pub mod build;
+#[path = "../commands/clean.rs"]
+pub mod clean;
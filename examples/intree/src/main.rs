@@ -0,0 +1,10 @@
+mod generated {
+    pub mod commands;
+}
+
+use generated::commands::*;
+
+fn main() {
+    build::run();
+    clean::run();
+}
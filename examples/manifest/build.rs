@@ -0,0 +1,3 @@
+fn main() {
+    proc_use::UseBuilder::from_manifest().write_outputs();
+}
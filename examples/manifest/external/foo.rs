@@ -0,0 +1,3 @@
+pub fn foo() -> &'static str {
+    "foo"
+}
@@ -0,0 +1,7 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+fn main() {
+    let mut message = String::new();
+    write!(message, "Hello from {} and {}!", aliased::foo(), bar()).unwrap();
+    println!("{}", message);
+}
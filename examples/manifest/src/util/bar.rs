@@ -0,0 +1,3 @@
+pub fn bar() -> &'static str {
+    "bar"
+}
@@ -3,19 +3,16 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
-    let plugin = false; // Change me!
-    
     let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
 
-    let mut override_watch = vec!["src/reference.rs"];
     let mut usebuilder = UseBuilder::new();
-    
-    if plugin {
-	override_watch.push("src/plugin.rs");
-	usebuilder.use_file("src/plugin.rs".into(), "*".into());
+    usebuilder.use_layered(&["src/plugin", "src/reference"], "*.rs", "*".into())
+	.write_to_file_all(out_path.join("proc_use.rs"));
+
+    println!("cargo:rerun-if-env-changed=REPORT_OVERRIDES");
+    if env::var_os("REPORT_OVERRIDES").is_some() {
+	for (shadowing, shadowed) in usebuilder.overrides() {
+	    println!("cargo:warning={} overrides {}", shadowing.display(), shadowed.display());
+	}
     }
-    
-    overrider_build::watch_files(override_watch);
-    usebuilder.use_file("src/reference.rs".into(), "*".into())
-	.write_to_file_all(out_path.join("proc_use.rs"));   
 }
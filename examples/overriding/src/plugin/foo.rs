@@ -1,6 +1,3 @@
-use overrider::*;
-
-#[override_default]
 pub fn foo() {
     println!("Overriden foo");
 }
@@ -1,6 +1,3 @@
-use overrider::*;
-
-#[default]
 pub fn foo() {
     println!("Default foo");
 }
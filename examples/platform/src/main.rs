@@ -0,0 +1,8 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+fn main() {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    println!("Hello from {}!", os_name());
+    #[cfg(debug_assertions)]
+    probe_debug::probe();
+}
@@ -0,0 +1,3 @@
+pub fn os_name() -> &'static str {
+    "macos"
+}
@@ -0,0 +1,3 @@
+pub fn probe() {
+    println!("debug build");
+}
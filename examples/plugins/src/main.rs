@@ -0,0 +1,9 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+fn main() {
+    println!("Plugins: {}", env!("PROC_USE_MODULES"));
+    #[cfg(proc_use_has_greet)]
+    greet::run();
+    #[cfg(proc_use_has_shout)]
+    shout::run();
+}
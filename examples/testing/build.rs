@@ -0,0 +1,11 @@
+use proc_use::UseBuilder;
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    UseBuilder::new()
+	.test_glob("tests/cases/*.rs", "crate::run_case", "case")
+	.test_fixture_glob("tests/fixtures/*.txt", "crate::check_fixture")
+	.write_to_file_all(out_path.join("proc_use.rs"));
+}
@@ -0,0 +1,15 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+#[cfg(test)]
+fn run_case(case: fn() -> u32) {
+    assert_eq!(case() % 2, 0, "case should return an even number");
+}
+
+#[cfg(test)]
+fn check_fixture(path: &str, contents: &str) {
+    assert!(contents.trim().ends_with("ok"), "fixture '{}' is not ok", path);
+}
+
+fn main() {
+    println!("Run `cargo test` to run each case.");
+}
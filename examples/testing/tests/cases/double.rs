@@ -0,0 +1,3 @@
+pub fn case() -> u32 {
+    21 * 2
+}
@@ -0,0 +1,3 @@
+pub fn case() -> u32 {
+    4 * 4
+}
@@ -0,0 +1,3 @@
+pub fn case() -> u32 {
+    std::mem::size_of::<u64>() as u32
+}
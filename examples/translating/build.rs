@@ -0,0 +1,25 @@
+use proc_use::UseBuilder;
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Turns each `key = "value"` line of a command description into a constant.
+fn translate(path: &Path, contents: &str) -> Result<String, String> {
+    contents.lines()
+	.filter(|line| !line.trim().is_empty())
+	.map(|line| match line.split_once('=') {
+	    Some((key, value)) => Ok(format!("pub const {}: &str = {};",
+					     key.trim().to_uppercase(), value.trim())),
+	    None => Err(format!("expected `key = \"value\"` in {}", path.display())),
+	})
+	.collect::<Result<Vec<_>, _>>()
+	.map(|consts| consts.join("\n"))
+}
+
+fn main() {
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let profile = env::var("PROFILE").unwrap();
+    UseBuilder::new()
+	.mod_translate_glob("commands/*.toml", translate)
+	.mod_source("built", &format!("pub const PROFILE: &str = {:?};", profile))
+	.write_to_file_all(out_path.join("proc_use.rs"));
+}
@@ -0,0 +1,7 @@
+include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
+
+fn main() {
+    println!("built in {} mode", built::PROFILE);
+    println!("{}: {}", greet::NAME, greet::HELP);
+    println!("{}: {}", farewell::NAME, farewell::HELP);
+}
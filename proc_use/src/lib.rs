@@ -9,7 +9,7 @@
 //!
 //! # Quick Example
 //! project_root/src/main.rs
-//! ```
+//! ```ignore
 //! include!(concat!(env!("OUT_DIR"), "/proc_use.rs"));
 //! fn main() {
 //!     foo();
@@ -23,26 +23,328 @@
 //! }
 //! ```
 ////////////////////////////////////////////////////////////////////////////////
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Write;
 use itertools::Itertools;
 use glob::glob;
+#[cfg(all(feature = "serde", feature = "sha2"))]
+use sha2::{Digest, Sha256};
+
+mod spec;
+pub use spec::{Input, OutputKind, Plan, PlannedModule, Spec};
+#[cfg(feature = "migrate")]
+mod migrate;
+#[cfg(feature = "migrate")]
+pub use migrate::{migrate, Migration};
+
+/// How an asset is included by `asset_glob`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    /// Include the asset as a `&str` with `include_str!`.
+    Str,
+    /// Include the asset as a `&[u8]` with `include_bytes!`.
+    Bytes,
+}
+
+/// An asset to be included by the generated asset constants.
+struct Asset {
+    /// The name of the generated constant.
+    name: String,
+    /// The key of the asset in the asset index, the path as it was globbed.
+    key: String,
+    /// The canonical path to the asset.
+    path: PathBuf,
+    /// Whether the asset is included as `&str` or `&[u8]`.
+    kind: AssetKind,
+}
+
+/// How modules in platform or profile specific paths are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TargetMode {
+    /// Place a matching `#[cfg]` on the mod and use statements.
+    Cfg,
+    /// Leave out modules which do not match the target being built.
+    Filter,
+}
+
+/// The `target_os` values recognised in paths.
+const TARGET_OSES: &[&str] = &["linux", "macos", "windows", "android", "ios", "freebsd",
+			       "netbsd", "openbsd", "dragonfly", "solaris", "illumos",
+			       "fuchsia", "redox", "wasi", "emscripten"];
+
+/// The `target_arch` values recognised in paths.
+const TARGET_ARCHES: &[&str] = &["x86", "x86_64", "arm", "aarch64", "mips", "mips64",
+				 "powerpc", "powerpc64", "riscv32", "riscv64", "s390x",
+				 "sparc64", "wasm32", "wasm64"];
+
+/// The keywords which can only be used as names in their raw form, eg `r#type`.
+const KEYWORDS: &[&str] = &["as", "break", "const", "continue", "else", "enum", "extern", "false",
+			    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+			    "mut", "pub", "ref", "return", "static", "struct", "trait", "true",
+			    "type", "unsafe", "use", "where", "while", "async", "await", "dyn",
+			    "abstract", "become", "box", "do", "final", "macro", "override",
+			    "priv", "typeof", "unsized", "virtual", "yield", "try"];
+
+/// The marker at the top of files written by `write_in_tree`.
+const IN_TREE_MARKER: &str =
+    "// @generated by proc_use. Do not edit; changes will be overwritten.";
+
+/// A generated test function.
+struct TestFn {
+    /// The name of the test function.
+    name: String,
+    /// The path to the module the test runs, if any.
+    module: Option<PathBuf>,
+    /// The canonical path to the fixture the test runs, if any.
+    fixture: Option<PathBuf>,
+    /// The call made by the test, or for a fixture the harness it is passed to.
+    call: String,
+}
+
+/// A module to be declared by the generated mod statements.
+struct Module {
+    /// The name of the generated mod.
+    name: String,
+    /// The canonical path to the module's source file.
+    path: PathBuf,
+    /// Extra attributes placed above the mod statement, eg `#[cfg(test)]`.
+    attrs: Vec<String>,
+    /// The canonical directory of the glob the module was found by, if any.
+    root: Option<PathBuf>,
+}
 
 /// The struct to represent the builder for proc_use.
 pub struct UseBuilder {
-    /// The mod statements to be generated.
-    mod_stmts: Vec<String>,
-    /// The use statements to be generated.
-    use_stmts: Vec<String>,
+    /// The modules to be generated.
+    modules: Vec<Module>,
+    /// The use statements to be generated, with the path to the module each one uses from.
+    use_stmts: Vec<(Option<PathBuf>, String)>,
+    /// The generated test functions.
+    test_fns: Vec<TestFn>,
+    /// The modules of the benchmark registry, with the path to each bench function.
+    benches: Vec<(Module, String)>,
+    /// The type of the bench functions in the benchmark registry.
+    bench_ty: String,
+    /// The asset constants to be generated.
+    assets: Vec<Asset>,
+    /// The names of the modules written to `OUT_DIR`, with what each one was generated from.
+    generated: Vec<(String, String)>,
+    /// The paths to emit `cargo:rerun-if-changed` for.
+    rerun: Vec<PathBuf>,
+    /// The files shadowed by a higher priority layer, as `(shadowing, shadowed)`.
+    overrides: Vec<(PathBuf, PathBuf)>,
+    /// The environment variable listing which modules to select.
+    env_filter: Option<String>,
+    /// How modules in platform or profile specific paths are handled.
+    target_mode: Option<TargetMode>,
+    /// Whether to emit rustc cfgs and env vars describing the generated modules.
+    emit_cfgs: bool,
+    /// The visibility of the generated mod and use statements, eg `pub(crate)`.
+    vis: String,
+    /// The files written by `write_outputs`, relative to `OUT_DIR`.
+    outputs: Vec<(OutputKind, PathBuf)>,
+    /// The files in the source tree written by `write_outputs`.
+    in_tree: Vec<PathBuf>,
+    /// The files, globs, layers and crates added, as recorded for `spec`.
+    inputs: Vec<Input>,
     /// Whether the import is used or not.
     unused: bool, // #[allow(unused_imports)] ?
 }
 
+impl Default for UseBuilder {
+    fn default() -> Self {
+	Self::new()
+    }
+}
+
 impl UseBuilder {
     /// Returns a UserBuilder with no mod or use statemetns and unused defaulted to true.
     pub fn new() -> Self {
-	Self{mod_stmts: Vec::new(), use_stmts: Vec::new(), unused: true}
+	Self{modules: Vec::new(), use_stmts: Vec::new(), test_fns: Vec::new(),
+	     benches: Vec::new(), bench_ty: "fn()".to_string(), assets: Vec::new(),
+	     generated: Vec::new(), rerun: Vec::new(), overrides: Vec::new(), env_filter: None,
+	     target_mode: None, emit_cfgs: false, vis: String::new(), outputs: Vec::new(),
+	     in_tree: Vec::new(), inputs: Vec::new(), unused: true}
+    }
+
+    /// Returns a UseBuilder configured from `proc_use.toml` in the package directory, or if
+    /// there is none, from the `[package.metadata.proc_use]` table of `Cargo.toml`. See
+    /// `Spec::from_toml` for the format. The configuration file is watched with
+    /// `cargo:rerun-if-changed`, along with the inputs as `from_spec` describes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// // in build.rs
+    /// proc_use::UseBuilder::from_manifest().write_outputs();
+    /// ```
+    pub fn from_manifest() -> Self {
+	let dir = match std::env::var("CARGO_MANIFEST_DIR") {
+	    Ok(dir) => PathBuf::from(dir),
+	    Err(_) => PathBuf::from("."),
+	};
+	let spec = Spec::from_manifest_dir(&dir);
+	let mut builder = Self::from_spec(&spec);
+	let config = dir.join("proc_use.toml");
+	if config.is_file() {
+	    builder.rerun.push(config);
+	} else {
+	    builder.rerun.push(dir.join("Cargo.toml"));
+	}
+	builder
+    }
+
+    /// Returns a UseBuilder configured from a `Spec`. Each input is added with the matching
+    /// builder method, so file inputs and the directories of glob inputs are watched with
+    /// `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The configuration to apply.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::{Spec, UseBuilder};
+    /// let spec = Spec::from_toml(&r#"
+    ///     [[modules]]
+    ///     glob = "../examples/globbing/src/util/*.rs"
+    ///
+    ///     [[modules]]
+    ///     file = "../examples/globbing/src/core/core.rs"
+    /// "#.parse().unwrap());
+    /// let directives = UseBuilder::from_spec(&spec).directives();
+    /// assert!(directives.contains(
+    ///         &"cargo:rerun-if-changed=../examples/globbing/src/util".to_string()));
+    /// assert!(directives.contains(
+    ///         &"cargo:rerun-if-changed=../examples/globbing/src/core/core.rs".to_string()));
+    /// ```
+    pub fn from_spec(spec: &Spec) -> Self {
+	let mut builder = Self::new();
+	for input in spec.inputs.iter() {
+	    match input {
+		Input::File{path, use_stmt, alias} => {
+		    match (use_stmt, alias) {
+			(None, None) => builder.mod_file(path.into()),
+			(Some(use_stmt), None) => builder.use_file(path.into(), use_stmt.clone()),
+			(None, Some(alias)) => builder.mod_file_alias(path.into(), alias),
+			(Some(use_stmt), Some(alias)) =>
+			    builder.use_file_alias(path.into(), use_stmt.clone(), alias),
+		    };
+		},
+		Input::Glob{pattern, use_stmt, alias} => {
+		    match (use_stmt, alias) {
+			(None, None) => builder.mod_glob(pattern),
+			(Some(use_stmt), None) => builder.use_glob(pattern, use_stmt.clone()),
+			(None, Some(alias)) => builder.mod_glob_alias(pattern, alias),
+			(Some(use_stmt), Some(alias)) =>
+			    builder.use_glob_alias(pattern, use_stmt.clone(), alias),
+		    };
+		},
+		Input::Layered{layers, pattern, use_stmt} => {
+		    let layers: Vec<&str> = layers.iter().map(String::as_str).collect();
+		    builder.layered(&layers, pattern, use_stmt.clone());
+		},
+		Input::Crate{use_stmt} => {
+		    builder.use_crate(use_stmt.clone());
+		},
+	    }
+	}
+	if let Some(vis) = &spec.visibility {
+	    builder.visibility(vis);
+	}
+	if spec.warn_unused {
+	    builder.warn_unused();
+	}
+	if let Some(var) = &spec.env_filter {
+	    builder.env_filter(var);
+	}
+	match spec.target.as_deref() {
+	    Some("cfg") => { builder.target_cfg(); },
+	    Some("filter") => { builder.target_filter(); },
+	    Some(target) => panic!("Invalid target mode '{}'. Expected \"cfg\" or \"filter\".",
+				   target),
+	    None => {},
+	}
+	if spec.emit_cfgs {
+	    builder.emit_cfgs();
+	}
+	for (kind, path) in spec.outputs.iter() {
+	    builder.output(*kind, path.into());
+	}
+	for path in spec.in_tree.iter() {
+	    builder.in_tree(path.into());
+	}
+	builder
+    }
+
+    /// Returns the configuration of the builder: the files, globs, layers and crates added,
+    /// and the options set. Tests, benchmarks, assets, translated files and generated code are
+    /// not part of the configuration.
+    pub fn spec(&self) -> Spec {
+	Spec{
+	    inputs: self.inputs.clone(),
+	    visibility: if self.vis.is_empty() { None } else { Some(self.vis.clone()) },
+	    warn_unused: !self.unused,
+	    env_filter: self.env_filter.clone(),
+	    target: self.target_mode.map(|mode| match mode {
+		TargetMode::Cfg => "cfg".to_string(),
+		TargetMode::Filter => "filter".to_string(),
+	    }),
+	    emit_cfgs: self.emit_cfgs,
+	    outputs: self.outputs.iter()
+		.map(|(kind, path)| (*kind, path.to_string_lossy().to_string()))
+		.collect(),
+	    in_tree: self.in_tree.iter()
+		.map(|path| path.to_string_lossy().to_string())
+		.collect(),
+	}
+    }
+
+    /// Returns the fully resolved plan of what the builder will generate: every module found,
+    /// with its name, attributes and use statements, the input it was found by, and why it
+    /// was left out if it was.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "serde")] {
+    /// # use proc_use::UseBuilder;
+    /// let plan = UseBuilder::new().use_file("src/lib.rs".into(), "*".into()).plan();
+    /// assert_eq!(plan.modules[0].uses, ["use lib::*;"]);
+    /// println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+    /// # }
+    /// ```
+    pub fn plan(&self) -> Plan {
+	let exclusions = self.exclusions();
+	let origins = self.origins();
+	Plan{
+	    modules: self.modules.iter()
+		.map(|module| PlannedModule{
+		    name: module.name.clone(),
+		    path: module.path.to_string_lossy().to_string(),
+		    attrs: self.module_attrs(module),
+		    visibility: self.vis.clone(),
+		    uses: self.use_stmts.iter()
+			.filter(|(path, _)| path.as_ref() == Some(&module.path))
+			.map(|(_, use_stmt)| use_stmt.clone())
+			.collect(),
+		    origin: origins.iter()
+			.find(|(path, _)| *path == module.path)
+			.map(|(_, origin)| origin.clone()),
+		    excluded: exclusions.iter()
+			.find(|(path, _)| *path == module.path)
+			.map(|(_, reason)| reason.clone()),
+		})
+		.collect(),
+	    crates: self.use_stmts.iter()
+		.filter(|(path, _)| path.is_none())
+		.map(|(_, use_stmt)| use_stmt.clone())
+		.collect(),
+	    outputs: self.spec().outputs,
+	    in_tree: self.spec().in_tree,
+	}
     }
 
     /// Adds a file to mod to the builder.
@@ -51,7 +353,8 @@ impl UseBuilder {
     ///
     /// * `file` - A PathBuf to the file that needs to be modded.
     pub fn mod_file(&mut self, file: PathBuf) -> &mut Self {
-	self.file(file, None, None);
+	self.record_file(&file, None, None);
+	self.file(file, None, None, None);
 	self
     }
 
@@ -62,7 +365,8 @@ impl UseBuilder {
     /// * `file` - A PathBuf to the file that needs to be modded.
     /// * `use_stmt` - A String that holds the use pattern for the file.
     pub fn use_file(&mut self, file: PathBuf, use_stmt: String) -> &mut Self {
-	self.file(file, Some(use_stmt), None);
+	self.record_file(&file, Some(&use_stmt), None);
+	self.file(file, Some(use_stmt), None, None);
 	self
     }
     
@@ -73,7 +377,8 @@ impl UseBuilder {
     /// * `file` - A PathBuf to the file that needs to be modded.
     /// * `alias` - Alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
     pub fn mod_file_alias(&mut self, file: PathBuf, alias: &str) -> &mut Self {
-	self.file(file, None, Some(alias.to_string()));
+	self.record_file(&file, None, Some(alias));
+	self.file(file, None, Some(alias.to_string()), None);
 	self
     }
     
@@ -85,7 +390,42 @@ impl UseBuilder {
     /// * `use_stmt` - A String that holds the use pattern for the file.
     /// * `alias` - Alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
     pub fn use_file_alias(&mut self, file: PathBuf, use_stmt: String, alias: &str) -> &mut Self {
-	self.file(file, Some(use_stmt), Some(alias.to_string()));
+	self.record_file(&file, Some(&use_stmt), Some(alias));
+	self.file(file, Some(use_stmt), Some(alias.to_string()), None);
+	self
+    }
+
+    /// Adds generated code to mod to the builder. The code is written to `OUT_DIR` and modded
+    /// like a file. Reusing the name of another generated module panics before it is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the generated mod, an identifier other than a keyword.
+    /// * `code` - The Rust code of the module.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use proc_use::UseBuilder;
+    /// let builder = UseBuilder::new()
+    ///         .mod_source("table", "pub const TABLE: [u8; 2] = [1, 2];");
+    /// ```
+    pub fn mod_source(&mut self, name: &str, code: &str) -> &mut Self {
+	self.source(name, code, None);
+	self
+    }
+
+    /// Adds generated code to use to the builder, and use pattern. The code is written to
+    /// `OUT_DIR` and used like a file. Reusing the name of another generated module panics
+    /// before it is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the generated mod, an identifier other than a keyword.
+    /// * `code` - The Rust code of the module.
+    /// * `use_stmt` - A String that holds the use pattern for the module.
+    pub fn use_source(&mut self, name: &str, code: &str, use_stmt: String) -> &mut Self {
+	self.source(name, code, Some(use_stmt));
 	self
     }
 
@@ -95,7 +435,8 @@ impl UseBuilder {
     ///
     /// * `use_stmt` - A String that holds the use pattern for the crate.
     pub fn use_crate(&mut self, use_stmt: String) -> &mut Self {
-	self.use_stmts.push(format!("use {};", use_stmt));
+	self.inputs.push(Input::Crate{use_stmt: use_stmt.clone()});
+	self.use_stmts.push((None, format!("use {};", use_stmt)));
 	self
     }
 
@@ -108,13 +449,16 @@ impl UseBuilder {
     /// # Example
     ///
     /// ```
+    /// # use proc_use::UseBuilder;
     /// let builder = UseBuilder::new()
     ///         .mod_glob("src/util/*.rs");
     /// ```
     pub fn mod_glob(&mut self, globstring: &str) -> &mut Self {
+	self.record_glob(globstring, None, None);
+	let root = glob_dir(globstring);
 	for entry in glob(globstring).expect("Failed to read glob pattern") {
 	    match entry {
-		Ok(path) => self.file(path, None, None),
+		Ok(path) => self.file(path, None, None, Some(&root)),
 		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
 	    }
 	}
@@ -130,13 +474,16 @@ impl UseBuilder {
     /// # Example
     ///
     /// ```
+    /// # use proc_use::UseBuilder;
     /// let builder = UseBuilder::new()
     ///         .use_glob("src/util/*.rs", "*".into());
     /// ```
     pub fn use_glob(&mut self, globstring: &str, use_stmt: String) -> &mut Self {
+	self.record_glob(globstring, Some(&use_stmt), None);
+	let root = glob_dir(globstring);
 	for entry in glob(globstring).expect("Failed to read glob pattern") {
 	    match entry {
-		Ok(path) => self.file(path, Some(use_stmt.clone()), None),
+		Ok(path) => self.file(path, Some(use_stmt.clone()), None, Some(&root)),
 		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
 	    }
 	}
@@ -152,14 +499,17 @@ impl UseBuilder {
     /// # Example
     ///
     /// ```
+    /// # use proc_use::UseBuilder;
     /// let builder = UseBuilder::new()
     ///         .mod_glob("src/util/*.rs");
     /// ```
     /// * `alias` - Alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
     pub fn mod_glob_alias(&mut self, globstring: &str, alias: &str) -> &mut Self {
+	self.record_glob(globstring, None, Some(alias));
+	let root = glob_dir(globstring);
 	for entry in glob(globstring).expect("Failed to read glob pattern") {
 	    match entry {
-		Ok(path) => self.file(path, None, Some(alias.to_string())),
+		Ok(path) => self.file(path, None, Some(alias.to_string()), Some(&root)),
 		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
 	    }
 	}
@@ -174,20 +524,351 @@ impl UseBuilder {
     /// # Example
     ///
     /// ```
+    /// # use proc_use::UseBuilder;
     /// let builder = UseBuilder::new()
     ///         .use_glob("src/util/*.rs", "*".into());
     /// ```
     /// * `alias` - Alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
     pub fn use_glob_alias(&mut self, globstring: &str, use_stmt: String, alias: &str) -> &mut Self {
+	self.record_glob(globstring, Some(&use_stmt), Some(alias));
+	let root = glob_dir(globstring);
 	for entry in glob(globstring).expect("Failed to read glob pattern") {
 	    match entry {
-		Ok(path) => self.file(path, Some(use_stmt.clone()), Some(alias.to_string())),
+		Ok(path) => self.file(path, Some(use_stmt.clone()), Some(alias.to_string()), Some(&root)),
+		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
+	    }
+	}
+	self
+    }
+
+    /// Glob layered source roots for files to mod. The glob is matched inside each layer, and a
+    /// file in a higher priority layer shadows the file with the same relative path in every
+    /// lower priority layer. Every layer is watched with `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The source roots, from highest to lowest priority.
+    /// * `globstring` - The glob pattern string to match, relative to each layer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // local/foo.rs shadows vendor/foo.rs, which shadows default/foo.rs
+    /// let builder = UseBuilder::new()
+    ///         .mod_layered(&["local", "vendor", "default"], "*.rs");
+    /// ```
+    pub fn mod_layered(&mut self, layers: &[&str], globstring: &str) -> &mut Self {
+	self.layered(layers, globstring, None);
+	self
+    }
+
+    /// Glob layered source roots for files to use. The glob is matched inside each layer, and
+    /// a file in a higher priority layer shadows the file with the same relative path in every
+    /// lower priority layer. Every layer is watched with `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The source roots, from highest to lowest priority.
+    /// * `globstring` - The glob pattern string to match, relative to each layer.
+    /// * `use_stmt` - A String that holds the use pattern for the files.
+    pub fn use_layered(&mut self, layers: &[&str], globstring: &str,
+		       use_stmt: String) -> &mut Self {
+	self.layered(layers, globstring, Some(use_stmt));
+	self
+    }
+
+    /// Returns the files shadowed by `mod_layered` or `use_layered`, as `(shadowing, shadowed)`
+    /// pairs.
+    pub fn overrides(&self) -> &[(PathBuf, PathBuf)] {
+	&self.overrides
+    }
+
+    /// Glob a file path for Rust test cases. Each matched file is modded under `#[cfg(test)]`
+    /// and gets its own `#[test]` function, named after the file, which passes the case's
+    /// entry function to a harness. A file named after a keyword, eg `type.rs`, is named with
+    /// a raw identifier, `r#type`.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `harness` - Path to the harness function, eg `crate::run_case`.
+    /// * `entry` - Name of the function each case file exposes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // generates `#[test] fn foo() { crate::run_case(foo::case); }` for tests/cases/foo.rs
+    /// let builder = UseBuilder::new()
+    ///         .test_glob("tests/cases/*.rs", "crate::run_case", "case");
+    /// ```
+    pub fn test_glob(&mut self, globstring: &str, harness: &str, entry: &str) -> &mut Self {
+	let root = glob_dir(globstring);
+	self.rerun.push(root.clone());
+	for entry_path in glob(globstring).expect("Failed to read glob pattern") {
+	    match entry_path {
+		Ok(path) => {
+		    let name = case_ident(&path);
+		    let module = self.file_with_attrs(path, None, Some(name.clone()),
+						      vec!["#[cfg(test)]".to_string()],
+						      Some(&root));
+		    self.test_fn(&name, Some(module), None,
+				 format!("{}({}::{})", harness, name, entry));
+		},
 		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
 	    }
 	}
 	self
     }
 
+    /// Glob a file path for test fixtures. Each matched file gets its own `#[test]` function,
+    /// named after the file, which passes the fixture's path and contents to a harness.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `harness` - Path to the harness function, taking a `&str` path and `&str` contents.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// let builder = UseBuilder::new()
+    ///         .test_fixture_glob("tests/fixtures/*.txt", "crate::check_fixture");
+    /// ```
+    pub fn test_fixture_glob(&mut self, globstring: &str, harness: &str) -> &mut Self {
+	self.rerun.push(glob_dir(globstring));
+	for entry in glob(globstring).expect("Failed to read glob pattern") {
+	    match entry {
+		Ok(path) => {
+		    let path = match path.canonicalize() {
+			Ok(path) => path,
+			Err(err) => panic!("Could not canonicalize file '{}': {}",
+					   path.to_string_lossy(), err),
+		    };
+		    let name = case_ident(&path);
+		    self.test_fn(&name, None, Some(path), harness.to_string());
+		},
+		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
+	    }
+	}
+	self
+    }
+
+    /// Glob a file path for benchmarks. Each matched file is modded and its bench function
+    /// is added to the benchmark registry written by `write_to_file_bench`.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `bench_fn` - Name of the bench function each file exposes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // generates `("foo", foo::bench)` in `BENCHES` for benches/algos/foo.rs
+    /// let builder = UseBuilder::new()
+    ///         .bench_glob("benches/algos/*.rs", "bench");
+    /// ```
+    pub fn bench_glob(&mut self, globstring: &str, bench_fn: &str) -> &mut Self {
+	self.rerun.push(glob_dir(globstring));
+	for entry in glob(globstring).expect("Failed to read glob pattern") {
+	    match entry {
+		Ok(path) => {
+		    let module = module(path, None, Vec::new(), None);
+		    if self.benches.iter().any(|(bench, _)| bench.name == module.name) {
+			panic!("Duplicate benchmark '{}'. Probable cause: \
+				two files with the same name were globbed.", module.name);
+		    }
+		    let bench_path = format!("{}::{}", module.name, bench_fn);
+		    self.benches.push((module, bench_path));
+		},
+		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
+	    }
+	}
+	self
+    }
+
+    /// Sets the type of the bench functions in the benchmark registry. Defaults to `fn()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bench_ty` - The function type, eg `fn(&mut Criterion)`.
+    pub fn bench_type(&mut self, bench_ty: &str) -> &mut Self {
+	self.bench_ty = bench_ty.to_string();
+	self
+    }
+
+    /// Glob a file path for assets. Each matched file becomes a constant named after the file,
+    /// eg `assets/sql/create_users.sql` becomes `CREATE_USERS`, and is listed in an `ASSETS`
+    /// index of `(path, bytes)` pairs. Every asset, and the directory being globbed, is watched
+    /// with `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `kind` - Whether to include the assets as `&str` or `&[u8]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::{AssetKind, UseBuilder};
+    /// let builder = UseBuilder::new()
+    ///         .asset_glob("assets/sql/*.sql", AssetKind::Str);
+    /// ```
+    pub fn asset_glob(&mut self, globstring: &str, kind: AssetKind) -> &mut Self {
+	self.rerun.push(glob_dir(globstring));
+	for entry in glob(globstring).expect("Failed to read glob pattern") {
+	    match entry {
+		Ok(path) => {
+		    let name = stem_ident(&path).to_uppercase();
+		    if self.assets.iter().any(|asset| asset.name == name) {
+			panic!("Duplicate asset '{}'. Probable cause: \
+				two files with the same name were globbed.", name);
+		    }
+		    let key = path.to_string_lossy().to_string();
+		    let path = match path.canonicalize() {
+			Ok(path) => path,
+			Err(err) => panic!("Could not canonicalize file '{}': {}",
+					   path.to_string_lossy(), err),
+		    };
+		    self.rerun.push(path.clone());
+		    self.assets.push(Asset{name, key, path, kind});
+		},
+		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
+	    }
+	}
+	self
+    }
+
+    /// Glob a file path for non-Rust source files to translate and mod. Each matched file is
+    /// passed to `translator`, and the Rust code it returns is written to `OUT_DIR` and modded
+    /// under the file's name. Every source file, and the directory being globbed, is watched
+    /// with `cargo:rerun-if-changed`. Two matched files with the same name, eg `a/x.idl` and
+    /// `b/x.idl`, panic before any file is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `translator` - Translates a file's path and contents into Rust code.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use proc_use::UseBuilder;
+    /// let builder = UseBuilder::new()
+    ///         .mod_translate_glob("commands/*.toml", |_path, contents| {
+    ///             Ok::<_, String>(format!("pub const SPEC: &str = {:?};", contents))
+    ///         });
+    /// ```
+    pub fn mod_translate_glob<F, E>(&mut self, globstring: &str, translator: F) -> &mut Self
+    where F: Fn(&Path, &str) -> Result<String, E>, E: std::fmt::Display {
+	self.translate_glob(globstring, None, translator);
+	self
+    }
+
+    /// Glob a file path for non-Rust source files to translate and use. Each matched file is
+    /// passed to `translator`, and the Rust code it returns is written to `OUT_DIR` and used
+    /// under the file's name. Every source file, and the directory being globbed, is watched
+    /// with `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `use_stmt` - A String that holds the use pattern for the files.
+    /// * `translator` - Translates a file's path and contents into Rust code.
+    pub fn use_translate_glob<F, E>(&mut self, globstring: &str, use_stmt: String,
+				    translator: F) -> &mut Self
+    where F: Fn(&Path, &str) -> Result<String, E>, E: std::fmt::Display {
+	self.translate_glob(globstring, Some(use_stmt), translator);
+	self
+    }
+
+    /// Selects modules with an environment variable. The variable holds a comma separated
+    /// list of module names: if any names are listed, only those modules are generated, and
+    /// names prefixed with `!` are left out. An unset or empty variable selects every module.
+    /// The variable is watched with `cargo:rerun-if-env-changed`, and naming a module that was
+    /// not found panics when the output is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `var` - The name of the environment variable.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // PROC_USE_PLUGINS=foo,bar selects foo and bar, PROC_USE_PLUGINS=!baz leaves out baz
+    /// let builder = UseBuilder::new()
+    ///         .use_glob("src/plugins/*.rs", "*".into())
+    ///         .env_filter("PROC_USE_PLUGINS");
+    /// ```
+    pub fn env_filter(&mut self, var: &str) -> &mut Self {
+	self.env_filter = Some(var.to_string());
+	self
+    }
+
+    /// Places a `#[cfg]` on modules in platform or profile specific paths. A directory directly
+    /// under the directory being globbed named, or a file name ending in `_` and, a `target_os`
+    /// such as `linux` or a `target_arch` such as `x86_64` is only built for that target.
+    /// Likewise `debug` is only built with `debug_assertions`, and `release` only without.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // src/platform/linux/term.rs becomes #[cfg(target_os = "linux")] mod term;
+    /// // src/platform/probe_debug.rs becomes #[cfg(debug_assertions)] mod probe_debug;
+    /// let builder = UseBuilder::new()
+    ///         .mod_glob("src/platform/**/*.rs")
+    ///         .target_cfg();
+    /// ```
+    pub fn target_cfg(&mut self) -> &mut Self {
+	self.target_mode = Some(TargetMode::Cfg);
+	self
+    }
+
+    /// Leaves out modules in platform or profile specific paths which do not match the target
+    /// being built, as read from the `CARGO_CFG_*` variables. Paths are matched the same way
+    /// as `target_cfg`.
+    pub fn target_filter(&mut self) -> &mut Self {
+	self.target_mode = Some(TargetMode::Filter);
+	self
+    }
+
+    /// Emits a `proc_use_has_<name>` rustc cfg for each generated module built for the current
+    /// target, and a `PROC_USE_MODULES` env var listing them separated by commas, so regular
+    /// code can check which modules were discovered. Modules only built for tests, or for
+    /// another target with `target_cfg`, are left out. Every module found, including ones left
+    /// out by a filter, is declared with `cargo:rustc-check-cfg`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// // code can now use #[cfg(proc_use_has_foo)] and env!("PROC_USE_MODULES")
+    /// let builder = UseBuilder::new()
+    ///         .use_glob("src/plugins/*.rs", "*".into())
+    ///         .emit_cfgs();
+    /// ```
+    pub fn emit_cfgs(&mut self) -> &mut Self {
+	self.emit_cfgs = true;
+	self
+    }
+
+    /// Sets the visibility of the generated mod statements, and of the use statements for
+    /// modded files. Defaults to private.
+    ///
+    /// # Arguments
+    ///
+    /// * `vis` - The visibility, eg `pub` or `pub(crate)`.
+    pub fn visibility(&mut self, vis: &str) -> &mut Self {
+	self.vis = vis.to_string();
+	self
+    }
+
     /// Disables the unused import error for each import.
     pub fn allow_unused(&mut self) -> &mut Self {
 	self.unused = true;
@@ -200,19 +881,133 @@ impl UseBuilder {
 	self
     }
 
+    /// Adds a file to be written by `write_outputs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What is written to the file.
+    /// * `path` - A PathBuf to the file, relative to `OUT_DIR`.
+    pub fn output(&mut self, kind: OutputKind, path: PathBuf) -> &mut Self {
+	self.outputs.push((kind, path));
+	self
+    }
+
+    /// Adds a file in the source tree to be written by `write_outputs`, as by `write_in_tree`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file, relative to the package directory.
+    pub fn in_tree(&mut self, path: PathBuf) -> &mut Self {
+	self.in_tree.push(path);
+	self
+    }
+
+    /// Renders what would be written to a file of the given kind, with absolute paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What is to be rendered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::{OutputKind, UseBuilder};
+    /// let mods = UseBuilder::new().mod_file("src/lib.rs".into()).render(OutputKind::Mod);
+    /// assert!(mods.ends_with("mod lib;"));
+    /// ```
+    pub fn render(&self, kind: OutputKind) -> String {
+	match kind {
+	    OutputKind::All => self.all_items(None),
+	    OutputKind::Mod => self.mod_items(None),
+	    OutputKind::Use => self.use_items(),
+	    OutputKind::Test => self.test_items(None),
+	    OutputKind::Bench => self.bench_items(),
+	    OutputKind::Asset => self.asset_items(None),
+	    OutputKind::Manifest => self.manifest_json(),
+	}
+    }
+
+    /// Returns the `cargo:` directives printed when a file is written: the files and
+    /// directories to rerun the build script on, as every glob's directory is watched for new
+    /// files, the environment variables to rerun it on, and with `emit_cfgs`, the cfgs and env
+    /// vars describing the generated modules.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// let directives = UseBuilder::new()
+    ///         .mod_glob("../examples/globbing/src/util/*.rs")
+    ///         .mod_file("../examples/globbing/src/core/core.rs".into())
+    ///         .directives();
+    /// assert!(directives.contains(
+    ///         &"cargo:rerun-if-changed=../examples/globbing/src/util".to_string()));
+    /// assert!(directives.contains(
+    ///         &"cargo:rerun-if-changed=../examples/globbing/src/core/core.rs".to_string()));
+    /// ```
+    pub fn directives(&self) -> Vec<String> {
+	let mut directives: Vec<String> = self.rerun.iter()
+	    .unique()
+	    .map(|rerun| format!("cargo:rerun-if-changed={}", rerun.to_string_lossy()))
+	    .collect();
+	if let Some(var) = &self.env_filter {
+	    directives.push(format!("cargo:rerun-if-env-changed={}", var));
+	}
+	if self.emit_cfgs {
+	    let excluded = self.excluded();
+	    for name in self.modules.iter().map(|module| cfg_name(&module.name)).unique() {
+		directives.push(format!("cargo:rustc-check-cfg=cfg(proc_use_has_{})", name));
+	    }
+	    let selected: Vec<&str> = self.modules.iter()
+		.filter(|module| !excluded.contains(&module.path))
+		.filter(|module| !module.attrs.iter().any(|attr| attr == "#[cfg(test)]"))
+		.filter(|module| self.target_mode != Some(TargetMode::Cfg)
+			|| target_cfgs(module).iter().all(|(key, value)| target_matches(key, value)))
+		.map(|module| cfg_name(&module.name))
+		.unique()
+		.collect();
+	    for name in selected.iter() {
+		directives.push(format!("cargo:rustc-cfg=proc_use_has_{}", name));
+	    }
+	    directives.push(format!("cargo:rustc-env=PROC_USE_MODULES={}", selected.iter().join(",")));
+	}
+	directives
+    }
+
+    /// Writes every file added with `output` to `OUT_DIR`, and every file added with `in_tree`
+    /// to the source tree.
+    pub fn write_outputs(&mut self) -> &mut Self {
+	let out_dir = match std::env::var("OUT_DIR") {
+	    Ok(out_dir) => PathBuf::from(out_dir),
+	    Err(err) => panic!("Could not read OUT_DIR: {}. Probable cause: \
+				not running from a build script.", err),
+	};
+	for (kind, path) in self.outputs.clone() {
+	    let path = out_dir.join(path);
+	    match kind {
+		OutputKind::All => self.write_to_file_all(path),
+		OutputKind::Mod => self.write_to_file_mod(path),
+		OutputKind::Use => self.write_to_file_use(path),
+		OutputKind::Test => self.write_to_file_test(path),
+		OutputKind::Bench => self.write_to_file_bench(path),
+		OutputKind::Asset => self.write_to_file_asset(path),
+		OutputKind::Manifest => self.write_to_file_manifest(path),
+	    };
+	}
+	for path in self.in_tree.clone() {
+	    self.write_in_tree(path);
+	}
+	self
+    }
+
     /// Writes the use statements to a file.
     ///
     /// # Arguments
     ///
     /// * `path` - A PathBuf to the file that is to be written.
     pub fn write_to_file_use(&mut self, path: PathBuf) -> &mut Self {
-	self.write_to_file(path, 
-			   if self.unused {
-			       self.use_stmts.iter()
-				   .map(|s| format!("#[allow(unused_imports)]\n{}", s)).join("\n")
-			   } else {
-			       self.use_stmts.join("\n")
-			   }.as_bytes());
+	let contents = self.use_items();
+	self.write_to_file(path, contents.as_bytes());
 	self
     }
 
@@ -222,28 +1017,494 @@ impl UseBuilder {
     ///
     /// * `path` - A PathBuf to the file that is to be written.
     pub fn write_to_file_mod(&mut self, path: PathBuf) -> &mut Self {
-	self.write_to_file(path, self.mod_stmts.join("\n").as_bytes());
+	let contents = self.mod_items(None);
+	self.write_to_file(path, contents.as_bytes());
+	self
+    }
+
+    /// Writes the generated test functions to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be written.
+    pub fn write_to_file_test(&mut self, path: PathBuf) -> &mut Self {
+	let contents = self.test_items(None);
+	self.write_to_file(path, contents.as_bytes());
+	self
+    }
+
+    /// Writes the benchmark modules and a `BENCHES` registry of `(name, bench_fn)` pairs to a
+    /// file, along with a `Bench` alias for the bench function type. This is meant to be
+    /// included by a `harness = false` bench target.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be written.
+    pub fn write_to_file_bench(&mut self, path: PathBuf) -> &mut Self {
+	let contents = self.bench_items();
+	self.write_to_file(path, contents.as_bytes());
+	self
+    }
+
+    /// Writes the asset constants and the `ASSETS` index to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be written.
+    pub fn write_to_file_asset(&mut self, path: PathBuf) -> &mut Self {
+	let contents = self.asset_items(None);
+	self.write_to_file(path, contents.as_bytes());
+	self
+    }
+
+    /// Writes a JSON manifest of the generated modules to a file, so tools can find out which
+    /// modules were compiled in without parsing Rust. Each module is listed with its name,
+    /// source path, visibility, cfgs and use statements, and with the `sha2` feature the
+    /// SHA-256 of its contents. Requires the `serde` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be written.
+    ///
+    /// # Example
+    ///
+    /// ```json
+    /// {"modules": [
+    ///   {"cfgs": ["target_os = \"linux\""], "hash": "sha256:2c26b4...", "name": "foo",
+    ///    "path": "/project/src/util/foo.rs", "uses": ["use foo::*;"], "visibility": ""}
+    /// ]}
+    /// ```
+    pub fn write_to_file_manifest(&mut self, path: PathBuf) -> &mut Self {
+	let contents = self.manifest_json();
+	self.write_to_file(path, contents.as_bytes());
 	self
     }
 
-    /// Writes the mod and use statements to a file.
+    /// Writes the mod and use statements, along with any generated tests and assets, to a file.
     ///
     /// # Arguments
     ///
     /// * `path` - A PathBuf to the file that is to be written.
     pub fn write_to_file_all(&mut self, path: PathBuf) -> &mut Self {
-	self.write_to_file(path,
-			   self.mod_stmts.iter().chain(std::iter::once(
-			       &if self.unused {
-				   self.use_stmts.iter()
-				       .map(|s| format!("#[allow(unused_imports)]\n{}", s)).join("\n")
-			       } else {
-				   self.use_stmts.join("\n")
-			       }
-			   )).join("\n").as_bytes());
+	let contents = self.all_items(None);
+	self.write_to_file(path, contents.as_bytes());
 	self
     }
-    
+
+    /// Writes the mod and use statements, along with any generated tests and assets, to a file
+    /// checked into the source tree, so IDEs and code review can see them. Paths are written
+    /// relative to the file, and it starts with a marker saying it is generated. The file is
+    /// only rewritten when its contents change. Pair with `check_in_tree` to catch stale files
+    /// in CI. As the file must not depend on how the crate is built, this panics with
+    /// `target_filter` or `env_filter`; use `target_cfg` to write platform specific modules.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be written, eg `src/generated/plugins.rs`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use proc_use::UseBuilder;
+    /// // in build.rs, with `mod generated { pub mod plugins; }` in src/main.rs
+    /// let mut builder = UseBuilder::new();
+    /// builder.use_glob("src/plugins/*.rs", "*".into());
+    /// if std::env::var_os("CI").is_some() {
+    ///     builder.check_in_tree("src/generated/plugins.rs".into());
+    /// } else {
+    ///     builder.write_in_tree("src/generated/plugins.rs".into());
+    /// }
+    /// ```
+    pub fn write_in_tree(&mut self, path: PathBuf) -> &mut Self {
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+	    if let Err(err) = std::fs::create_dir_all(dir) {
+		panic!("Could not create directory '{}': {}", dir.to_string_lossy(), err);
+	    }
+	}
+	if self.in_tree_up_to_date(&path) {
+	    self.emit_directives();
+	} else {
+	    let contents = self.in_tree_contents(&path);
+	    self.write_to_file(path, contents.as_bytes());
+	}
+	self
+    }
+
+    /// Checks that a file written by `write_in_tree` is up to date, panicking if it is stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A PathBuf to the file that is to be checked.
+    pub fn check_in_tree(&mut self, path: PathBuf) -> &mut Self {
+	self.emit_directives();
+	if !self.in_tree_up_to_date(&path) {
+	    panic!("In-tree file '{}' is out of date. Probable cause: the modules changed \
+		    since it was generated, rebuild with `write_in_tree` to update it.",
+		   path.to_string_lossy());
+	}
+	self
+    }
+
+    /// Returns whether a file written by `write_in_tree` is up to date. Useful for checking
+    /// generated files from a test.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file that is to be checked.
+    pub fn in_tree_up_to_date(&self, path: &Path) -> bool {
+	self.check_in_tree_filters(path);
+	match std::fs::read_to_string(path) {
+	    Ok(contents) => contents == self.in_tree_contents(path),
+	    Err(_) => false,
+	}
+    }
+
+    /// Renders what `write_in_tree` would write to a file. The file's directory must exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file that is to be written.
+    pub fn in_tree_contents(&self, path: &Path) -> String {
+	self.check_in_tree_filters(path);
+	let dir = match path.parent() {
+	    Some(dir) if !dir.as_os_str().is_empty() => dir,
+	    _ => Path::new("."),
+	};
+	let base = match dir.canonicalize() {
+	    Ok(base) => base,
+	    Err(err) => panic!("Could not canonicalize directory '{}': {}",
+			       dir.to_string_lossy(), err),
+	};
+	format!("{}\n{}\n", IN_TREE_MARKER, self.all_items(Some(&base)))
+    }
+
+    /// Helper function to check no filter makes an in-tree file depend on how the crate is
+    /// built, as builds with different targets or environments would keep rewriting it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The in-tree file.
+    fn check_in_tree_filters(&self, path: &Path) {
+	if self.target_mode == Some(TargetMode::Filter) {
+	    panic!("Could not render in-tree file '{}'. Probable cause: `target_filter` makes it \
+		    depend on the target being built, use `target_cfg` instead.",
+		   path.to_string_lossy());
+	}
+	if let Some(var) = &self.env_filter {
+	    panic!("Could not render in-tree file '{}'. Probable cause: `env_filter` makes it \
+		    depend on {}, write it to OUT_DIR instead.", path.to_string_lossy(), var);
+	}
+    }
+
+    /// Helper function to render the mod and use statements, along with any generated tests
+    /// and assets.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory paths are written relative to, or `None` for absolute paths.
+    fn all_items(&self, base: Option<&Path>) -> String {
+	std::iter::once(self.mod_items(base))
+	    .chain(std::iter::once(self.use_items()))
+	    .chain(std::iter::once(self.test_items(base)))
+	    .chain(std::iter::once(self.asset_items(base)))
+	    .filter(|s| !s.is_empty())
+	    .join("\n")
+    }
+
+    /// Helper function to render the mod statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory paths are written relative to, or `None` for absolute paths.
+    fn mod_items(&self, base: Option<&Path>) -> String {
+	let excluded = self.excluded();
+	let modules: Vec<&Module> = self.modules.iter()
+	    .filter(|module| !excluded.contains(&module.path))
+	    .collect();
+	for (i, module) in modules.iter().enumerate() {
+	    let clash = modules[..i].iter()
+		.filter(|other| other.name == module.name)
+		.find(|other| self.target_mode != Some(TargetMode::Cfg)
+		      || !exclusive(module, other));
+	    if let Some(other) = clash {
+		panic!("Duplicate module '{}' from '{}' and '{}'. Probable cause: \
+			two files with the same name, consider an alias.",
+		       module.name, other.path.to_string_lossy(), module.path.to_string_lossy());
+	    }
+	}
+	modules.into_iter().map(|module| self.mod_item(module, base)).join("\n")
+    }
+
+    /// Helper function to render the benchmark modules and registry.
+    fn bench_items(&self) -> String {
+	self.benches.iter()
+	    .map(|(module, _)| self.mod_item(module, None))
+	    .chain(std::iter::once(format!(
+		"pub type Bench = {};\npub const BENCHES: &[(&str, Bench)] = &[\n{}];",
+		self.bench_ty,
+		self.benches.iter()
+		    .map(|(module, bench_path)| format!("    (\"{}\", {}),\n", module.name, bench_path))
+		    .join(""))))
+	    .join("\n")
+    }
+
+    /// Helper function to render the generated test functions.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory paths are written relative to, or `None` for absolute paths.
+    fn test_items(&self, base: Option<&Path>) -> String {
+	let excluded = self.excluded();
+	self.test_fns.iter()
+	    .filter(|test_fn| !test_fn.module.iter().any(|path| excluded.contains(path)))
+	    .map(|test_fn| {
+		let body = match &test_fn.fixture {
+		    Some(fixture) => {
+			let fixture = path_str(fixture, base);
+			format!("{}({:?}, include_str!({:?}));", test_fn.call, fixture, fixture)
+		    },
+		    None => format!("{};", test_fn.call),
+		};
+		format!("#[test]\nfn {}() {{\n    {}\n}}", test_fn.name, body)
+	    })
+	    .join("\n")
+    }
+
+    /// Helper function to render the asset constants and index.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory paths are written relative to, or `None` for absolute paths.
+    fn asset_items(&self, base: Option<&Path>) -> String {
+	if self.assets.is_empty() {
+	    return String::new();
+	}
+	self.assets.iter()
+	    .map(|asset| match asset.kind {
+		AssetKind::Str => format!("pub const {}: &str = include_str!({:?});",
+					  asset.name, path_str(&asset.path, base)),
+		AssetKind::Bytes => format!("pub const {}: &[u8] = include_bytes!({:?});",
+					    asset.name, path_str(&asset.path, base)),
+	    })
+	    .chain(std::iter::once(format!(
+		"pub const ASSETS: &[(&str, &[u8])] = &[\n{}];",
+		self.assets.iter()
+		    .map(|asset| match asset.kind {
+			AssetKind::Str => format!("    ({:?}, {}.as_bytes()),\n",
+						  asset.key, asset.name),
+			AssetKind::Bytes => format!("    ({:?}, {}),\n", asset.key, asset.name),
+		    })
+		    .join(""))))
+	    .join("\n")
+    }
+
+    /// Helper function to render the JSON manifest of the generated modules.
+    #[cfg(feature = "serde")]
+    fn manifest_json(&self) -> String {
+	let modules: Vec<serde_json::Value> = self.plan().modules.into_iter()
+	    .filter(|module| module.excluded.is_none())
+	    .map(|module| {
+		let cfgs: Vec<&str> = module.attrs.iter()
+		    .filter(|attr| attr.starts_with("#[cfg(") && attr.ends_with(")]"))
+		    .map(|attr| &attr["#[cfg(".len()..attr.len() - ")]".len()])
+		    .collect();
+		#[allow(unused_mut)]
+		let mut entry = serde_json::json!({
+		    "name": module.name,
+		    "path": module.path,
+		    "visibility": module.visibility,
+		    "cfgs": cfgs,
+		    "uses": module.uses,
+		});
+		#[cfg(feature = "sha2")]
+		{
+		    let hash = match std::fs::read(&module.path) {
+			Ok(contents) => Sha256::digest(&contents).iter()
+			    .map(|byte| format!("{:02x}", byte)).join(""),
+			Err(err) => panic!("Could not read file '{}': {}", module.path, err),
+		    };
+		    entry["hash"] = format!("sha256:{}", hash).into();
+		}
+		entry
+	    })
+	    .collect();
+	format!("{:#}\n", serde_json::json!({"modules": modules}))
+    }
+
+    /// Helper function to render the JSON manifest of the generated modules, which needs the
+    /// `serde` feature.
+    #[cfg(not(feature = "serde"))]
+    fn manifest_json(&self) -> String {
+	panic!("Could not write the JSON manifest. Probable cause: \
+		the `serde` feature of proc_use is not enabled.");
+    }
+
+    /// Helper function to render the use statements.
+    fn use_items(&self) -> String {
+	let excluded = self.excluded();
+	self.use_stmts.iter()
+	    .filter(|(module, _)| !module.iter().any(|path| excluded.contains(path)))
+	    .map(|(module, s)| {
+		let attrs = self.modules.iter()
+		    .filter(|other| module.as_ref() == Some(&other.path))
+		    .flat_map(|module| self.module_attrs(module))
+		    .chain(if self.unused { Some("#[allow(unused_imports)]".to_string()) }
+			   else { None });
+		let vis = if module.is_some() { self.vis_prefix() } else { String::new() };
+		attrs.map(|attr| format!("{}\n", attr)).join("") + &vis + s
+	    })
+	    .join("\n")
+    }
+
+    /// Helper function to find the paths to the modules excluded by the module filters.
+    fn excluded(&self) -> Vec<PathBuf> {
+	self.exclusions().into_iter().map(|(path, _)| path).collect()
+    }
+
+    /// Helper function to find the paths to the modules excluded by the module filters, with
+    /// the reason each was excluded.
+    fn exclusions(&self) -> Vec<(PathBuf, String)> {
+	let mut exclusions = Vec::new();
+	if let Some(var) = &self.env_filter {
+	    let value = std::env::var(var).unwrap_or_default();
+	    let (exclude, include): (Vec<&str>, Vec<&str>) = value.split(',')
+		.map(str::trim)
+		.filter(|name| !name.is_empty())
+		.partition(|name| name.starts_with('!'));
+	    let exclude: Vec<&str> = exclude.iter()
+		.map(|name| name.trim_start_matches('!')).collect();
+	    for name in include.iter().chain(exclude.iter()) {
+		if !self.modules.iter().any(|module| module.name == *name) {
+		    panic!("Module '{}' requested by {} was not found. Probable cause: \
+			    no file exists for it.", name, var);
+		}
+	    }
+	    for module in self.modules.iter() {
+		if exclude.contains(&module.name.as_str()) {
+		    exclusions.push((module.path.clone(), format!("excluded by {}", var)));
+		} else if !include.is_empty() && !include.contains(&module.name.as_str()) {
+		    exclusions.push((module.path.clone(), format!("not selected by {}", var)));
+		}
+	    }
+	}
+	if self.target_mode == Some(TargetMode::Filter) {
+	    for module in self.modules.iter() {
+		let unmet = target_cfgs(module).into_iter()
+		    .find(|(key, value)| !target_matches(key, value));
+		if let Some((key, value)) = unmet {
+		    if !exclusions.iter().any(|(path, _)| *path == module.path) {
+			exclusions.push((module.path.clone(),
+					 format!("target does not match {} = {:?}", key, value)));
+		    }
+		}
+	    }
+	}
+	exclusions
+    }
+
+    /// Helper function to find the input each file was found by, for `plan`. Every input is
+    /// globbed once, and a file found by several inputs is attributed to the first.
+    fn origins(&self) -> Vec<(PathBuf, String)> {
+	let canonical = |paths: Vec<PathBuf>| -> Vec<PathBuf> {
+	    paths.into_iter().filter_map(|path| path.canonicalize().ok()).collect()
+	};
+	let globbed = |pattern: &str| -> Vec<PathBuf> {
+	    match glob(pattern) {
+		Ok(paths) => canonical(paths.filter_map(Result::ok).collect()),
+		Err(_) => Vec::new(),
+	    }
+	};
+	let mut origins: Vec<(PathBuf, String)> = Vec::new();
+	for input in self.inputs.iter() {
+	    let paths = match input {
+		Input::File{path, ..} => canonical(vec![path.into()]),
+		Input::Glob{pattern, ..} => globbed(pattern),
+		Input::Layered{layers, pattern, ..} => layers.iter()
+		    .flat_map(|layer| globbed(&Path::new(layer).join(pattern).to_string_lossy()))
+		    .collect(),
+		Input::Crate{..} => Vec::new(),
+	    };
+	    for path in paths {
+		if !origins.iter().any(|(other, _)| *other == path) {
+		    origins.push((path, input.to_string()));
+		}
+	    }
+	}
+	origins
+    }
+
+    /// Helper function to find the attributes placed above a module's mod and use statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The module the attributes are for.
+    fn module_attrs(&self, module: &Module) -> Vec<String> {
+	let mut attrs = module.attrs.clone();
+	if self.target_mode == Some(TargetMode::Cfg) {
+	    attrs.extend(target_cfgs(module).into_iter()
+			 .map(|(key, value)| match key {
+			     "debug_assertions" if value == "false" =>
+				 "#[cfg(not(debug_assertions))]".to_string(),
+			     "debug_assertions" => "#[cfg(debug_assertions)]".to_string(),
+			     key => format!("#[cfg({} = {:?})]", key, value),
+			 }));
+	}
+	attrs
+    }
+
+    /// Helper function to render the mod statement of a module.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The module to be rendered.
+    /// * `base` - The directory paths are written relative to, or `None` for absolute paths.
+    fn mod_item(&self, module: &Module, base: Option<&Path>) -> String {
+	self.module_attrs(module).iter()
+	    .map(|attr| format!("{}\n", attr)).join("")
+	    + &format!("#[path = \"{}\"]\n{}mod {};",
+		       path_str(&module.path, base),
+		       self.vis_prefix(),
+		       module.name)
+    }
+
+    /// Helper function to render the visibility of generated mod and use statements,
+    /// followed by a space if there is one.
+    fn vis_prefix(&self) -> String {
+	if self.vis.is_empty() {
+	    String::new()
+	} else {
+	    format!("{} ", self.vis)
+	}
+    }
+
+    /// Helper function to record a file for `spec`, and watch it with `cargo:rerun-if-changed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file added.
+    /// * `use_stmt` - The use pattern for the file, if it is used.
+    /// * `alias` - Alternate name for the mod.
+    fn record_file(&mut self, file: &Path, use_stmt: Option<&String>, alias: Option<&str>) {
+	self.rerun.push(file.to_path_buf());
+	self.inputs.push(Input::File{path: file.to_string_lossy().to_string(),
+				     use_stmt: use_stmt.cloned(),
+				     alias: alias.map(str::to_string)});
+    }
+
+    /// Helper function to record a glob for `spec`, and watch the directory it searches with
+    /// `cargo:rerun-if-changed` so new files are picked up.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string added.
+    /// * `use_stmt` - The use pattern for the files, if they are used.
+    /// * `alias` - Alternate name for the mod.
+    fn record_glob(&mut self, globstring: &str, use_stmt: Option<&String>, alias: Option<&str>) {
+	self.rerun.push(glob_dir(globstring));
+	self.inputs.push(Input::Glob{pattern: globstring.to_string(),
+				     use_stmt: use_stmt.cloned(),
+				     alias: alias.map(str::to_string)});
+    }
 
     /// Helper function to canonicalizes a file, and adds use or mod statements.
     ///
@@ -252,36 +1513,174 @@ impl UseBuilder {
     /// * `file` - A PathBuf to the file that needs to be validated and moded/used.
     /// * `use_stmt` - A optional String that holds the use pattern for the file.
     /// * `alias` - Optional alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
-    fn file(&mut self, file: PathBuf, use_stmt: Option<String>, alias: Option<String>) {
-	let file = match file.canonicalize() {
-	    Ok(file) => file,
-	    Err(err) => panic!("Could not canonicalize file '{}': {}",
-			       file.to_string_lossy(), err),
-	};
-	let mod_opt = {
-	    if alias.is_some() {
-		alias
-	    } else {
-		file.file_stem().map(|o| (*o.to_string_lossy()).to_string())
-	    }
-	};
-	match (mod_opt, file.extension()) {
-	    (Some(mod_name), Some(ext)) if ext == "rs" => {
-		self.mod_stmts.push(format!("#[path = \"{}\"]\nmod {};",
-					    file.to_string_lossy(),
-					    mod_name));
-		if let Some(use_stmt) = use_stmt {
-		    self.use_stmts.push(format!("use {}::{};",
-						mod_name,
-						use_stmt));
+    /// * `root` - The directory of the glob the file was found by, if any.
+    fn file(&mut self, file: PathBuf, use_stmt: Option<String>, alias: Option<String>,
+	    root: Option<&Path>) {
+	self.file_with_attrs(file, use_stmt, alias, Vec::new(), root);
+    }
+
+    /// Helper function to add a file with extra attributes on its mod statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A PathBuf to the file that needs to be validated and moded/used.
+    /// * `use_stmt` - A optional String that holds the use pattern for the file.
+    /// * `alias` - Optional alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
+    /// * `attrs` - Attributes to be placed above the mod statement.
+    /// * `root` - The directory of the glob the file was found by, if any.
+    ///
+    /// Returns the canonical path to the file.
+    fn file_with_attrs(&mut self, file: PathBuf, use_stmt: Option<String>, alias: Option<String>,
+		       attrs: Vec<String>, root: Option<&Path>) -> PathBuf {
+	let module = module(file, alias, attrs, root);
+	let path = module.path.clone();
+	if let Some(use_stmt) = use_stmt {
+	    self.use_stmts.push((Some(path.clone()),
+				 format!("use {}::{};", module.name, use_stmt)));
+	}
+	self.modules.push(module);
+	path
+    }
+
+    /// Helper function to write generated code to `OUT_DIR`, and add use or mod statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the generated mod.
+    /// * `code` - The Rust code of the module.
+    /// * `use_stmt` - A optional String that holds the use pattern for the module.
+    fn source(&mut self, name: &str, code: &str, use_stmt: Option<String>) {
+	if name.is_empty() || name == "_" || name.starts_with(|c: char| c.is_ascii_digit())
+	    || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+	    panic!("Invalid module name '{}'. Probable cause: is not an identifier.", name);
+	}
+	if KEYWORDS.contains(&name) || ["self", "Self", "super", "crate"].contains(&name) {
+	    panic!("Invalid module name '{}'. Probable cause: is a keyword.", name);
+	}
+	let out_file = self.generate(name, "generated code", code);
+	self.file(out_file, use_stmt, None, None);
+    }
+
+    /// Helper function to reserve the name of a generated module, panicking if another module
+    /// was generated under the same name, as it would overwrite that module's code.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the generated module.
+    /// * `origin` - What the module is generated from, for error messages.
+    fn claim_generated(&mut self, name: &str, origin: &str) {
+	if let Some((_, other)) = self.generated.iter().find(|(generated, _)| generated == name) {
+	    panic!("Duplicate generated module '{}' from '{}' and '{}'. Probable cause: \
+		    two sources with the same name.", name, other, origin);
+	}
+	self.generated.push((name.to_string(), origin.to_string()));
+    }
+
+    /// Helper function to write a generated module to `OUT_DIR`, after checking its name is
+    /// not taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the generated module.
+    /// * `origin` - What the module is generated from, for error messages.
+    /// * `code` - The Rust code of the module.
+    fn generate(&mut self, name: &str, origin: &str, code: &str) -> PathBuf {
+	self.claim_generated(name, origin);
+	let out_file = generated_file(name);
+	write_file(&out_file, code.as_bytes());
+	out_file
+    }
+
+    /// Helper function to translate globbed files into generated modules.
+    ///
+    /// # Arguments
+    ///
+    /// * `globstring` - The glob pattern string to match.
+    /// * `use_stmt` - A optional String that holds the use pattern for the files.
+    /// * `translator` - Translates a file's path and contents into Rust code.
+    fn translate_glob<F, E>(&mut self, globstring: &str, use_stmt: Option<String>, translator: F)
+    where F: Fn(&Path, &str) -> Result<String, E>, E: std::fmt::Display {
+	self.rerun.push(glob_dir(globstring));
+	let paths: Vec<PathBuf> = glob(globstring).expect("Failed to read glob pattern")
+	    .map(|entry| match entry {
+		Ok(path) => path,
+		Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
+	    })
+	    .collect();
+	for path in paths.iter() {
+	    self.claim_generated(&stem_ident(path), &path.to_string_lossy());
+	}
+	for path in paths {
+	    let contents = match std::fs::read_to_string(&path) {
+		Ok(contents) => contents,
+		Err(err) => panic!("Could not read file '{}': {}", path.to_string_lossy(), err),
+	    };
+	    let code = match translator(&path, &contents) {
+		Ok(code) => code,
+		Err(err) => panic!("Could not translate file '{}': {}",
+				   path.to_string_lossy(), err),
+	    };
+	    let out_file = generated_file(&stem_ident(&path));
+	    write_file(&out_file, code.as_bytes());
+	    self.rerun.push(path);
+	    self.file(out_file, use_stmt.clone(), None, None);
+	}
+    }
+
+    /// Helper function to glob layered source roots, and add use or mod statements for the
+    /// highest priority file at each relative path.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The source roots, from highest to lowest priority.
+    /// * `globstring` - The glob pattern string to match, relative to each layer.
+    /// * `use_stmt` - A optional String that holds the use pattern for the files.
+    fn layered(&mut self, layers: &[&str], globstring: &str, use_stmt: Option<String>) {
+	self.inputs.push(Input::Layered{layers: layers.iter().map(|layer| layer.to_string()).collect(),
+					pattern: globstring.to_string(),
+					use_stmt: use_stmt.clone()});
+	let mut chosen: Vec<(PathBuf, PathBuf, PathBuf)> = Vec::new();
+	for layer in layers {
+	    let layer = Path::new(layer);
+	    self.rerun.push(layer.to_path_buf());
+	    let layer_glob = layer.join(globstring);
+	    for entry in glob(&layer_glob.to_string_lossy()).expect("Failed to read glob pattern") {
+		match entry {
+		    Ok(path) => {
+			let relative = match path.strip_prefix(layer) {
+			    Ok(relative) => relative.to_path_buf(),
+			    Err(_) => path.clone(),
+			};
+			match chosen.iter().find(|(other, _, _)| *other == relative) {
+			    Some((_, shadowing, _)) => self.overrides.push((shadowing.clone(), path)),
+			    None => chosen.push((relative, path,
+						 glob_dir(&layer_glob.to_string_lossy()))),
+			}
+		    },
+		    Err(e) => panic!("Could not resolve glob pattern: {:?}", e),
 		}
-	    },
-	    (Some(_), _) => panic!("Invalid file '{}'. Probable cause: \
-				    file is not a rust file.",
-				   file.to_string_lossy()),
-	    (None, _) => panic!("Invalid file '{}'. Probable cause: is not a regular file.",
-				file.to_string_lossy()),
+	    }
+	}
+	for (_, path, root) in chosen {
+	    self.file(path, use_stmt.clone(), None, Some(&root));
+	}
+    }
+
+    /// Helper function to add a generated test function.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the test function.
+    /// * `module` - The path to the module the test runs, if any.
+    /// * `fixture` - The canonical path to the fixture the test runs, if any.
+    /// * `call` - The call made by the test, or for a fixture the harness it is passed to.
+    fn test_fn(&mut self, name: &str, module: Option<PathBuf>, fixture: Option<PathBuf>,
+	       call: String) {
+	if self.test_fns.iter().any(|test_fn| test_fn.name == name) {
+	    panic!("Duplicate test case '{}'. Probable cause: \
+		    two files with the same name were globbed.", name);
 	}
+	self.test_fns.push(TestFn{name: name.to_string(), module, fixture, call});
     }
 
     /// Helper function to write to a file.
@@ -291,14 +1690,248 @@ impl UseBuilder {
     /// * `path` - A PathBuf to the file that needs to be modded.
     /// * `contents` - The contents to be written to a file.
     fn write_to_file(&mut self, path: PathBuf, contents: &[u8]) {
-	match File::create(&path) {
-	    Ok(mut file) => {
-		if let Err(err) = file.write_all(contents) {
-		    panic!("Could not write to file '{}': {}", path.to_string_lossy(), err);
-		}
-	    },
-	    Err(err) => panic!("Could not open file '{}' for writing: {}",
-			       path.to_string_lossy(), err),
+	self.emit_directives();
+	write_file(&path, contents);
+    }
+
+    /// Helper function to print the `cargo:` directives for the build script.
+    fn emit_directives(&self) {
+	for directive in self.directives() {
+	    println!("{}", directive);
 	}
     }
 }
+
+/// Helper function to render a path in generated code.
+///
+/// # Arguments
+///
+/// * `path` - The canonical path to render.
+/// * `base` - The canonical directory to render the path relative to, with `/` separators,
+///   or `None` to render it as is.
+fn path_str(path: &Path, base: Option<&Path>) -> String {
+    let base = match base {
+	Some(base) => base,
+	None => return path.to_string_lossy().to_string(),
+    };
+    let common = path.components().zip(base.components())
+	.take_while(|(a, b)| a == b)
+	.count();
+    if common == 0 {
+	return path.to_string_lossy().to_string();
+    }
+    (common..base.components().count()).map(|_| "..".to_string())
+	.chain(path.components().skip(common)
+	       .map(|component| component.as_os_str().to_string_lossy().to_string()))
+	.join("/")
+}
+
+/// Helper function to write to a file.
+///
+/// # Arguments
+///
+/// * `path` - The file that is to be written.
+/// * `contents` - The contents to be written to a file.
+fn write_file(path: &Path, contents: &[u8]) {
+    match File::create(path) {
+	Ok(mut file) => {
+	    if let Err(err) = file.write_all(contents) {
+		panic!("Could not write to file '{}': {}", path.to_string_lossy(), err);
+	    }
+	},
+	Err(err) => panic!("Could not open file '{}' for writing: {}",
+			   path.to_string_lossy(), err),
+    }
+}
+
+/// Helper function to find the path of a generated module in `OUT_DIR/proc_use`, creating
+/// the directory if needed.
+///
+/// # Arguments
+///
+/// * `name` - The name of the generated module.
+fn generated_file(name: &str) -> PathBuf {
+    let out_dir = match std::env::var("OUT_DIR") {
+	Ok(out_dir) => PathBuf::from(out_dir).join("proc_use"),
+	Err(err) => panic!("Could not read OUT_DIR: {}. Probable cause: \
+			    not running from a build script.", err),
+    };
+    if let Err(err) = std::fs::create_dir_all(&out_dir) {
+	panic!("Could not create directory '{}': {}", out_dir.to_string_lossy(), err);
+    }
+    out_dir.join(format!("{}.rs", name))
+}
+
+/// Helper function to derive an identifier from a file's stem, replacing characters
+/// that are not valid in identifiers.
+///
+/// # Arguments
+///
+/// * `file` - The file the identifier is derived from.
+fn stem_ident(file: &Path) -> String {
+    let stem = match file.file_stem() {
+	Some(stem) => stem.to_string_lossy(),
+	None => panic!("Invalid file '{}'. Probable cause: is not a regular file.",
+		       file.to_string_lossy()),
+    };
+    let name: String = stem.chars()
+	.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+	.collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+	format!("_{}", name)
+    } else {
+	name
+    }
+}
+
+/// Helper function to derive the name of a generated test from a file's stem, as `stem_ident`
+/// does, using a raw identifier for keywords, eg `r#type` for `type.rs`.
+///
+/// # Arguments
+///
+/// * `file` - The file the name is derived from.
+fn case_ident(file: &Path) -> String {
+    let name = stem_ident(file);
+    if ["self", "Self", "super", "crate", "_"].contains(&name.as_str()) {
+	panic!("Invalid file '{}'. Probable cause: `{}` cannot be used as a test name.",
+	       file.to_string_lossy(), name);
+    }
+    if KEYWORDS.contains(&name.as_str()) {
+	format!("r#{}", name)
+    } else {
+	name
+    }
+}
+
+/// Helper function to find the name of a module in its `proc_use_has_` cfg, without the `r#`
+/// of a raw identifier.
+///
+/// # Arguments
+///
+/// * `name` - The name of the module.
+fn cfg_name(name: &str) -> &str {
+    name.trim_start_matches("r#")
+}
+
+/// Helper function to canonicalize and validate a file as a module.
+///
+/// # Arguments
+///
+/// * `file` - A PathBuf to the file that needs to be validated.
+/// * `alias` - Optional alternate name for imported mod. Eg import `foo.rs` as `mod bar`.
+/// * `attrs` - Attributes to be placed above the mod statement.
+/// * `root` - The directory of the glob the file was found by, if any.
+fn module(file: PathBuf, alias: Option<String>, attrs: Vec<String>, root: Option<&Path>) -> Module {
+    let file = match file.canonicalize() {
+	Ok(file) => file,
+	Err(err) => panic!("Could not canonicalize file '{}': {}",
+			   file.to_string_lossy(), err),
+    };
+    let mod_opt = {
+	if alias.is_some() {
+	    alias
+	} else {
+	    file.file_stem().map(|o| (*o.to_string_lossy()).to_string())
+	}
+    };
+    match (mod_opt, file.extension()) {
+	(Some(name), Some(ext)) if ext == "rs" => {
+	    let root = root.and_then(|root| root.canonicalize().ok());
+	    Module{name, path: file, attrs, root}
+	},
+	(Some(_), _) => panic!("Invalid file '{}'. Probable cause: \
+				file is not a rust file.",
+			       file.to_string_lossy()),
+	(None, _) => panic!("Invalid file '{}'. Probable cause: is not a regular file.",
+			    file.to_string_lossy()),
+    }
+}
+
+/// Helper function to check whether two modules can never be built for the same target,
+/// ie their paths imply a different value for the same target condition.
+///
+/// # Arguments
+///
+/// * `module` - One module.
+/// * `other` - The other module.
+fn exclusive(module: &Module, other: &Module) -> bool {
+    let other_cfgs = target_cfgs(other);
+    target_cfgs(module).iter()
+	.any(|(key, value)| other_cfgs.iter().any(|(other_key, other_value)| {
+	    key == other_key && value != other_value
+	}))
+}
+
+/// Helper function to find the target conditions implied by a module's path, as
+/// `(cfg key, value)` pairs. Only the file name, and for globbed modules the directory
+/// directly under the glob's root, are considered, so eg a `debug` directory above the root
+/// or deeper down does not count.
+///
+/// # Arguments
+///
+/// * `module` - The module to find the conditions for.
+fn target_cfgs(module: &Module) -> Vec<(&'static str, String)> {
+    let dir = module.root.as_ref()
+	.and_then(|root| module.path.strip_prefix(root).ok())
+	.and_then(Path::parent)
+	.and_then(|parent| parent.components().next())
+	.map(|component| component.as_os_str().to_string_lossy().to_string());
+    let stem = module.path.file_stem().map(|stem| stem.to_string_lossy().to_string())
+	.unwrap_or_default();
+    let suffix = TARGET_OSES.iter().chain(TARGET_ARCHES.iter()).chain(&["debug", "release"])
+	.filter(|segment| stem.ends_with(&format!("_{}", segment)))
+	.max_by_key(|segment| segment.len())
+	.map(|segment| segment.to_string());
+    let mut cfgs = Vec::new();
+    for segment in dir.into_iter().chain(suffix) {
+	let cfg = if TARGET_OSES.contains(&segment.as_str()) {
+	    ("target_os", segment)
+	} else if TARGET_ARCHES.contains(&segment.as_str()) {
+	    ("target_arch", segment)
+	} else if segment == "debug" {
+	    ("debug_assertions", "true".to_string())
+	} else if segment == "release" {
+	    ("debug_assertions", "false".to_string())
+	} else {
+	    continue;
+	};
+	if !cfgs.contains(&cfg) {
+	    cfgs.push(cfg);
+	}
+    }
+    cfgs
+}
+
+/// Helper function to check a target condition against the target being built.
+///
+/// # Arguments
+///
+/// * `key` - The cfg key, eg `target_os`.
+/// * `value` - The value required by the module.
+fn target_matches(key: &str, value: &str) -> bool {
+    match key {
+	"debug_assertions" => std::env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some()
+	    == (value == "true"),
+	key => std::env::var(format!("CARGO_CFG_{}", key.to_uppercase()))
+	    .map(|target| target == value)
+	    .unwrap_or(true),
+    }
+}
+
+/// Helper function to find the directory a glob pattern searches, ie the longest leading
+/// path without any glob metacharacters.
+///
+/// # Arguments
+///
+/// * `globstring` - The glob pattern string.
+fn glob_dir(globstring: &str) -> PathBuf {
+    let dir: PathBuf = Path::new(globstring).components()
+	.take_while(|component| !component.as_os_str().to_string_lossy()
+		    .contains(['*', '?', '[']))
+	.collect();
+    if dir.as_os_str().is_empty() {
+	PathBuf::from(".")
+    } else {
+	dir
+    }
+}
@@ -0,0 +1,377 @@
+//! Conversion of hand-written `#[path]` mod lists to proc_use, behind the `migrate` feature.
+use crate::{Input, OutputKind, Spec};
+use glob::glob;
+use itertools::Itertools;
+use std::path::{Component, Path, PathBuf};
+use syn::spanned::Spanned;
+
+/// The result of `migrate`: the configuration equivalent to the mod and use statements that
+/// were converted, and the ones that were not.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Migration {
+    /// The configuration equivalent to the converted statements, with paths relative to the
+    /// current directory.
+    pub spec: Spec,
+    /// An equivalent `proc_use!` invocation, or `None` if nothing was converted.
+    pub inline: Option<String>,
+    /// The lines of the converted statements, which the generated code replaces, as the first
+    /// and last line of each run of consecutive lines.
+    pub converted: Vec<(usize, usize)>,
+    /// The line of each `#[path]` mod or matching use statement that could not be converted,
+    /// with the reason why.
+    pub unconverted: Vec<(usize, String)>,
+}
+
+impl Migration {
+    /// Renders a `build.rs` equivalent to the converted statements, writing
+    /// `OUT_DIR/proc_use.rs`.
+    pub fn build_rs(&self) -> String {
+	let calls = self.spec.inputs.iter()
+	    .filter_map(|input| match input {
+		Input::File{path, use_stmt, alias} => Some(match (use_stmt, alias) {
+		    (None, None) => format!(".mod_file({:?}.into())", path),
+		    (Some(use_stmt), None) => format!(".use_file({:?}.into(), {:?}.into())",
+						      path, use_stmt),
+		    (None, Some(alias)) => format!(".mod_file_alias({:?}.into(), {:?})",
+						   path, alias),
+		    (Some(use_stmt), Some(alias)) =>
+			format!(".use_file_alias({:?}.into(), {:?}.into(), {:?})",
+				path, use_stmt, alias),
+		}),
+		Input::Glob{pattern, use_stmt, ..} => Some(match use_stmt {
+		    None => format!(".mod_glob({:?})", pattern),
+		    Some(use_stmt) => format!(".use_glob({:?}, {:?}.into())", pattern, use_stmt),
+		}),
+		_ => None,
+	    })
+	    .chain(self.spec.visibility.iter().map(|vis| format!(".visibility({:?})", vis)))
+	    .map(|call| format!("\t{}\n", call))
+	    .join("");
+	format!("use proc_use::UseBuilder;\n\
+		 use std::env;\n\
+		 use std::path::PathBuf;\n\
+		 \n\
+		 fn main() {{\n\
+		 \x20   let out_path = PathBuf::from(env::var(\"OUT_DIR\").unwrap());\n\
+		 \x20   UseBuilder::new()\n\
+		 {}\
+		 \t.write_to_file_all(out_path.join(\"proc_use.rs\"));\n\
+		 }}\n", calls)
+    }
+}
+
+/// A `#[path]` mod found by `migrate`.
+struct Found {
+    /// The name of the mod.
+    name: String,
+    /// The path as written in the `#[path]` attribute.
+    attr_path: String,
+    /// The path relative to the current directory.
+    path: PathBuf,
+    /// The visibility of the mod, empty if private.
+    vis: String,
+    /// The use pattern for the mod, if a matching use statement was found.
+    use_stmt: Option<String>,
+    /// The line the mod statement starts on, with its attributes.
+    line: usize,
+    /// The line the mod statement ends on.
+    last_line: usize,
+}
+
+/// How a use statement relates to the `#[path]` mods found.
+enum UseRoot {
+    /// It uses `pattern` from within the mod `name`.
+    Module(String, String),
+    /// It names a mod found, but cannot be converted for the given reason.
+    Unsupported(String),
+    /// It does not name a mod found.
+    Other,
+}
+
+/// Reads a Rust file and converts its `#[path]` mod statements, and the use statements of
+/// those mods, into an equivalent proc_use configuration. Mods in the same directory become
+/// a glob when they cover every file in it and are used alike.
+///
+/// # Arguments
+///
+/// * `file` - The file to convert, relative to the package directory, eg `src/main.rs`.
+///
+/// # Example
+///
+/// ```no_run
+/// let migration = proc_use::migrate("src/main.rs".as_ref());
+/// println!("{}", migration.build_rs());
+/// for (line, reason) in migration.unconverted {
+///     eprintln!("src/main.rs:{}: {}", line, reason);
+/// }
+/// ```
+pub fn migrate(file: &Path) -> Migration {
+    let source = match std::fs::read_to_string(file) {
+	Ok(source) => source,
+	Err(err) => panic!("Could not read file '{}': {}", file.to_string_lossy(), err),
+    };
+    let syntax = match syn::parse_file(&source) {
+	Ok(syntax) => syntax,
+	Err(err) => panic!("Could not parse file '{}': {}", file.to_string_lossy(), err),
+    };
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    let mut migration = Migration::default();
+
+    let mut found: Vec<Found> = Vec::new();
+    for item in syntax.items.iter() {
+	if let syn::Item::Mod(item) = item {
+	    if let Some(found_mod) = found_mod(item, dir, &mut migration.unconverted) {
+		found.push(found_mod);
+	    }
+	}
+    }
+    let vis = found.first().map(|found_mod| found_mod.vis.clone()).unwrap_or_default();
+    found.retain(|found_mod| {
+	if found_mod.vis != vis {
+	    migration.unconverted.push((found_mod.line, format!(
+		"visibility `{}` differs from `{}`, only one visibility is supported",
+		found_mod.vis, vis)));
+	}
+	found_mod.vis == vis
+    });
+
+    for item in syntax.items.iter() {
+	let item = match item {
+	    syn::Item::Use(item) if item.leading_colon.is_none() => item,
+	    _ => continue,
+	};
+	let line = item.span().start().line;
+	match use_root(&item.tree, &found) {
+	    UseRoot::Module(name, pattern) => {
+		let found_mod = match found.iter_mut().find(|found_mod| found_mod.name == name) {
+		    Some(found_mod) => found_mod,
+		    None => continue,
+		};
+		if !item.attrs.is_empty() {
+		    migration.unconverted.push((line, "attributes on use statements are not \
+						       supported".to_string()));
+		} else if vis_str(&item.vis) != vis {
+		    migration.unconverted.push((line, format!(
+			"visibility `{}` differs from its mod", vis_str(&item.vis))));
+		} else if found_mod.use_stmt.is_some() {
+		    migration.unconverted.push((line, format!(
+			"only one use statement per mod is supported, `{}` already has one",
+			name)));
+		} else {
+		    found_mod.use_stmt = Some(pattern);
+		    migration.converted.push((line, item.span().end().line));
+		}
+	    },
+	    UseRoot::Unsupported(reason) => migration.unconverted.push((line, reason)),
+	    UseRoot::Other => {},
+	}
+    }
+
+    let mut grouped: Vec<&Path> = Vec::new();
+    for found_mod in found.iter() {
+	if grouped.contains(&found_mod.path.as_path()) {
+	    continue;
+	}
+	let mod_dir = found_mod.path.parent().unwrap_or_else(|| Path::new(""));
+	let pattern = slashed(&mod_dir.join("*.rs"));
+	let siblings: Vec<&Found> = found.iter()
+	    .filter(|other| other.path.parent() == Some(mod_dir))
+	    .collect();
+	let files: Vec<PathBuf> = match glob(&pattern) {
+	    Ok(paths) => paths.filter_map(Result::ok).map(|path| normalize(&path)).collect(),
+	    Err(_) => Vec::new(),
+	};
+	let globbable = siblings.len() > 1
+	    && siblings.iter().all(|other| Some(other.name.as_str()) == stem(&other.path))
+	    && siblings.iter().map(|other| &other.use_stmt).all_equal()
+	    && files.len() == siblings.len()
+	    && files.iter().all(|path| siblings.iter().any(|other| other.path == *path));
+	if globbable {
+	    grouped.extend(siblings.iter().map(|other| other.path.as_path()));
+	    migration.spec.inputs.push(Input::Glob{pattern, use_stmt: found_mod.use_stmt.clone(),
+						   alias: None});
+	} else {
+	    let alias = if Some(found_mod.name.as_str()) == stem(&found_mod.path) {
+		None
+	    } else {
+		Some(found_mod.name.clone())
+	    };
+	    migration.spec.inputs.push(Input::File{path: slashed(&found_mod.path),
+						   use_stmt: found_mod.use_stmt.clone(),
+						   alias});
+	}
+    }
+    if !vis.is_empty() {
+	migration.spec.visibility = Some(vis.clone());
+    }
+    migration.spec.outputs.push((OutputKind::All, "proc_use.rs".to_string()));
+
+    if !found.is_empty() {
+	let (mod_vis, use_vis) = if vis.is_empty() {
+	    (String::new(), String::new())
+	} else {
+	    (format!("{}, ", vis), format!("{} ", vis))
+	};
+	migration.inline = Some(format!(
+	    "proc_use_inline::proc_use! {{\n{}}}\n",
+	    found.iter()
+		.map(|found_mod| match &found_mod.use_stmt {
+		    Some(use_stmt) => format!("    #[mod({}{:?})]\n    {}use {}::{};\n",
+					      mod_vis, found_mod.attr_path, use_vis,
+					      found_mod.name, use_stmt),
+		    None if Some(found_mod.name.as_str()) == stem(&found_mod.path) =>
+			format!("    mod({}{:?});\n", mod_vis, found_mod.attr_path),
+		    None => format!("    mod({}{:?}, as = {:?});\n",
+				    mod_vis, found_mod.attr_path, found_mod.name),
+		})
+		.join("")));
+    }
+    migration.converted.extend(found.iter().map(|found_mod| (found_mod.line, found_mod.last_line)));
+    migration.converted.sort_unstable();
+    migration.converted = migration.converted.into_iter()
+	.coalesce(|(first, last), (next_first, next_last)| if next_first <= last + 1 {
+	    Ok((first, std::cmp::max(last, next_last)))
+	} else {
+	    Err(((first, last), (next_first, next_last)))
+	})
+	.collect();
+    migration.unconverted.sort();
+    migration
+}
+
+/// Helper function to read a `#[path]` mod statement, recording why if it cannot be converted.
+/// Returns `None` for mods without a `#[path]` attribute.
+///
+/// # Arguments
+///
+/// * `item` - The mod statement.
+/// * `dir` - The directory of the file it is in.
+/// * `unconverted` - Where to record why it cannot be converted.
+fn found_mod(item: &syn::ItemMod, dir: &Path,
+	     unconverted: &mut Vec<(usize, String)>) -> Option<Found> {
+    let attr = item.attrs.iter().find(|attr| attr.path.is_ident("path"))?;
+    let line = item.span().start().line;
+    let attr_path = match attr.parse_meta() {
+	Ok(syn::Meta::NameValue(syn::MetaNameValue{lit: syn::Lit::Str(lit), ..})) => lit.value(),
+	_ => {
+	    unconverted.push((line, "expected `#[path = \"...\"]`".to_string()));
+	    return None;
+	},
+    };
+    let path = normalize(&dir.join(&attr_path));
+    let reason = if item.attrs.len() > 1 {
+	Some("attributes other than `#[path]` are not supported".to_string())
+    } else if item.content.is_some() {
+	Some("mods with an inline body are not supported".to_string())
+    } else if path.extension() != Some("rs".as_ref()) || !path.is_file() {
+	Some(format!("'{}' is not an existing Rust file", path.to_string_lossy()))
+    } else {
+	None
+    };
+    if let Some(reason) = reason {
+	unconverted.push((line, reason));
+	return None;
+    }
+    Some(Found{name: item.ident.to_string(), attr_path, path, vis: vis_str(&item.vis),
+	       use_stmt: None, line, last_line: item.span().end().line})
+}
+
+/// Helper function to find how a use statement relates to the `#[path]` mods found.
+///
+/// # Arguments
+///
+/// * `tree` - The use tree of the statement.
+/// * `found` - The mods found.
+fn use_root(tree: &syn::UseTree, found: &[Found]) -> UseRoot {
+    let is_found = |ident: &syn::Ident| found.iter().any(|found_mod| ident == &found_mod.name);
+    match tree {
+	syn::UseTree::Path(path) if path.ident == "self" => use_root(&path.tree, found),
+	syn::UseTree::Path(path) if is_found(&path.ident) =>
+	    UseRoot::Module(path.ident.to_string(), use_tree_str(&path.tree)),
+	syn::UseTree::Name(syn::UseName{ident}) | syn::UseTree::Rename(syn::UseRename{ident, ..})
+	    if is_found(ident) =>
+	    UseRoot::Unsupported(format!("use statements must use items from within `{}`",
+					 ident)),
+	syn::UseTree::Group(group) if group.items.iter()
+	    .any(|tree| !matches!(use_root(tree, found), UseRoot::Other)) =>
+	    UseRoot::Unsupported("use groups spanning several mods are not supported"
+				 .to_string()),
+	_ => UseRoot::Other,
+    }
+}
+
+/// Helper function to render a use tree as written in a use pattern.
+///
+/// # Arguments
+///
+/// * `tree` - The use tree.
+fn use_tree_str(tree: &syn::UseTree) -> String {
+    match tree {
+	syn::UseTree::Path(path) => format!("{}::{}", path.ident, use_tree_str(&path.tree)),
+	syn::UseTree::Name(name) => name.ident.to_string(),
+	syn::UseTree::Rename(rename) => format!("{} as {}", rename.ident, rename.rename),
+	syn::UseTree::Glob(_) => "*".to_string(),
+	syn::UseTree::Group(group) =>
+	    format!("{{{}}}", group.items.iter().map(use_tree_str).join(", ")),
+    }
+}
+
+/// Helper function to render a visibility, empty if private.
+///
+/// # Arguments
+///
+/// * `vis` - The visibility.
+fn vis_str(vis: &syn::Visibility) -> String {
+    match vis {
+	syn::Visibility::Public(_) => "pub".to_string(),
+	syn::Visibility::Crate(_) => "crate".to_string(),
+	syn::Visibility::Restricted(restricted) => {
+	    let path = restricted.path.segments.iter()
+		.map(|segment| segment.ident.to_string())
+		.join("::");
+	    match restricted.in_token {
+		Some(_) => format!("pub(in {})", path),
+		None => format!("pub({})", path),
+	    }
+	},
+	syn::Visibility::Inherited => String::new(),
+    }
+}
+
+/// Helper function to find the file stem of a path.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+fn stem(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|stem| stem.to_str())
+}
+
+/// Helper function to render a path with `/` separators.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+fn slashed(path: &Path) -> String {
+    path.components().map(|component| component.as_os_str().to_string_lossy()).join("/")
+}
+
+/// Helper function to remove `.` components from a path, and `..` components following a
+/// directory, without touching the filesystem.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut components: Vec<Component> = Vec::new();
+    for component in path.components() {
+	match component {
+	    Component::CurDir => {},
+	    Component::ParentDir if matches!(components.last(), Some(Component::Normal(_))) => {
+		components.pop();
+	    },
+	    component => components.push(component),
+	}
+    }
+    components.iter().collect()
+}
@@ -0,0 +1,577 @@
+//! Declarative configuration for `UseBuilder`, as read from `proc_use.toml` or the
+//! `[package.metadata.proc_use]` table of `Cargo.toml`.
+use std::fmt;
+use std::path::Path;
+use toml::Value;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// What is written to an output file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum OutputKind {
+    /// The mod and use statements, along with any generated tests and assets.
+    All,
+    /// The mod statements.
+    Mod,
+    /// The use statements.
+    Use,
+    /// The generated test functions.
+    Test,
+    /// The benchmark modules and registry.
+    Bench,
+    /// The asset constants and index.
+    Asset,
+    /// The JSON manifest of the generated modules.
+    Manifest,
+}
+
+impl OutputKind {
+    /// The name of the output kind in a manifest.
+    pub fn name(&self) -> &'static str {
+	match self {
+	    OutputKind::All => "all",
+	    OutputKind::Mod => "mod",
+	    OutputKind::Use => "use",
+	    OutputKind::Test => "test",
+	    OutputKind::Bench => "bench",
+	    OutputKind::Asset => "asset",
+	    OutputKind::Manifest => "manifest",
+	}
+    }
+
+    /// Looks up an output kind by its name in a manifest.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the output kind, eg `all`.
+    pub fn from_name(name: &str) -> Option<Self> {
+	[OutputKind::All, OutputKind::Mod, OutputKind::Use,
+	 OutputKind::Test, OutputKind::Bench, OutputKind::Asset, OutputKind::Manifest].iter()
+	    .find(|kind| kind.name() == name)
+	    .copied()
+    }
+}
+
+/// A source of modules or use statements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// A single file, as added by `mod_file` or `use_file`.
+    File {
+	/// The path to the file.
+	path: String,
+	/// The use pattern for the file, if it is used.
+	use_stmt: Option<String>,
+	/// Alternate name for the mod.
+	alias: Option<String>,
+    },
+    /// A glob of files, as added by `mod_glob` or `use_glob`.
+    Glob {
+	/// The glob pattern string to match.
+	pattern: String,
+	/// The use pattern for the files, if they are used.
+	use_stmt: Option<String>,
+	/// Alternate name for the mod.
+	alias: Option<String>,
+    },
+    /// A glob of layered source roots, as added by `mod_layered` or `use_layered`.
+    Layered {
+	/// The source roots, from highest to lowest priority.
+	layers: Vec<String>,
+	/// The glob pattern string to match, relative to each layer.
+	pattern: String,
+	/// The use pattern for the files, if they are used.
+	use_stmt: Option<String>,
+    },
+    /// A crate to use, as added by `use_crate`.
+    Crate {
+	/// The use pattern for the crate.
+	use_stmt: String,
+    },
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Input::File{path, ..} => write!(f, "file `{}`", path),
+	    Input::Glob{pattern, ..} => write!(f, "glob `{}`", pattern),
+	    Input::Layered{layers, pattern, ..} =>
+		write!(f, "glob `{}` in layers `{}`", pattern, layers.join("`, `")),
+	    Input::Crate{use_stmt} => write!(f, "crate `{}`", use_stmt),
+	}
+    }
+}
+
+/// The configuration of a `UseBuilder`. With the `serde` feature, it can be saved and loaded
+/// in any format serde supports, laid out as in `proc_use.toml`.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// # use proc_use::{Spec, UseBuilder};
+/// let spec = UseBuilder::new()
+///         .use_glob("src/*.rs", "*".into())
+///         .use_crate("std::io::*".into())
+///         .visibility("pub(crate)")
+///         .spec();
+/// let json = serde_json::to_string(&spec).unwrap();
+/// assert_eq!(serde_json::from_str::<Spec>(&json).unwrap(), spec);
+/// let toml = toml::to_string(&spec).unwrap();
+/// assert_eq!(toml::from_str::<Spec>(&toml).unwrap(), spec);
+///
+/// // inputs keep their order, crates included
+/// let spec = UseBuilder::new()
+///         .use_crate("std::io::*".into())
+///         .use_file("src/lib.rs".into(), "*".into())
+///         .spec();
+/// let toml = toml::to_string(&spec).unwrap();
+/// assert_eq!(toml::from_str::<Spec>(&toml).unwrap(), spec);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Spec {
+    /// The sources of modules and use statements, in order.
+    pub inputs: Vec<Input>,
+    /// The visibility of the generated mod and use statements.
+    pub visibility: Option<String>,
+    /// Whether to warn about unused imports.
+    pub warn_unused: bool,
+    /// The environment variable listing which modules to select.
+    pub env_filter: Option<String>,
+    /// How modules in platform or profile specific paths are handled, `cfg` or `filter`.
+    pub target: Option<String>,
+    /// Whether to emit rustc cfgs and env vars describing the generated modules.
+    pub emit_cfgs: bool,
+    /// The files written by `write_outputs`, relative to `OUT_DIR`.
+    pub outputs: Vec<(OutputKind, String)>,
+    /// The files in the source tree written by `write_outputs`, relative to the package.
+    pub in_tree: Vec<String>,
+}
+
+impl Spec {
+    /// Reads the configuration for a package, from `proc_use.toml` if it exists, or else from
+    /// the `[package.metadata.proc_use]` table of `Cargo.toml`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The package directory.
+    pub fn from_manifest_dir(dir: &Path) -> Self {
+	let config = dir.join("proc_use.toml");
+	if config.is_file() {
+	    return Self::from_toml(&read_toml(&config));
+	}
+	let manifest = dir.join("Cargo.toml");
+	match read_toml(&manifest).get("package")
+	    .and_then(|package| package.get("metadata"))
+	    .and_then(|metadata| metadata.get("proc_use")) {
+		Some(table) => Self::from_toml(table),
+		None => panic!("Could not find proc_use configuration. Probable cause: \
+				neither '{}' nor [package.metadata.proc_use] in '{}' exist.",
+			       config.to_string_lossy(), manifest.to_string_lossy()),
+	    }
+    }
+
+    /// Reads the configuration from a TOML table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table holding the configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::Spec;
+    /// let spec = Spec::from_toml(&r#"
+    ///     visibility = "pub(crate)"  # optional, for mod and use statements
+    ///     warn_unused = false        # optional
+    ///     env_filter = "PLUGINS"     # optional, see `env_filter`
+    ///     target = "cfg"             # optional, "cfg" or "filter"
+    ///     emit_cfgs = false          # optional, see `emit_cfgs`
+    ///     crates = ["std::io::*"]    # optional, see `use_crate`, used after the modules
+    ///     in_tree = ["src/gen.rs"]   # optional, see `write_in_tree`
+    ///
+    ///     [[modules]]
+    ///     glob = "src/util/*.rs"     # or `file = "..."`
+    ///     use = "*"                  # optional
+    ///     alias = "util"             # optional
+    ///
+    ///     [[modules]]
+    ///     layers = ["src/plugin", "src/reference"]
+    ///     glob = "*.rs"
+    ///     use = "*"                  # optional
+    ///
+    ///     [[modules]]
+    ///     crate = "std::fmt::Write"  # a crate used in order, see `use_crate`
+    ///
+    ///     [output]                   # optional, defaults to all = "proc_use.rs"
+    ///     all = "proc_use.rs"        # or mod, use, test, bench, asset, manifest
+    /// "#.parse().unwrap());
+    /// assert_eq!(spec.inputs.len(), 4);
+    /// ```
+    ///
+    /// Unknown keys are rejected, so a misspelt option is not silently ignored:
+    ///
+    /// ```should_panic
+    /// # use proc_use::Spec;
+    /// Spec::from_toml(&r#"
+    ///     [[modules]]
+    ///     glob = "src/util/*.rs"
+    ///     usee = "*"
+    /// "#.parse().unwrap());
+    /// ```
+    pub fn from_toml(table: &Value) -> Self {
+	match Self::parse(table) {
+	    Ok(spec) => spec,
+	    Err(err) => panic!("{}", err),
+	}
+    }
+
+    /// Writes the configuration as a TOML table, in the format read by `from_toml`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use proc_use::UseBuilder;
+    /// let table = UseBuilder::new().use_glob("src/*.rs", "*".into()).spec().to_toml();
+    /// assert_eq!(table["modules"][0]["glob"].as_str(), Some("src/*.rs"));
+    /// ```
+    pub fn to_toml(&self) -> Value {
+	let mut table = toml::value::Table::new();
+	let string = |s: &String| Value::String(s.clone());
+	let strings = |strings: Vec<&String>| Value::Array(strings.into_iter().map(string).collect());
+	if let Some(vis) = &self.visibility {
+	    table.insert("visibility".into(), string(vis));
+	}
+	table.insert("warn_unused".into(), Value::Boolean(self.warn_unused));
+	if let Some(var) = &self.env_filter {
+	    table.insert("env_filter".into(), string(var));
+	}
+	if let Some(target) = &self.target {
+	    table.insert("target".into(), string(target));
+	}
+	table.insert("emit_cfgs".into(), Value::Boolean(self.emit_cfgs));
+	if !self.in_tree.is_empty() {
+	    table.insert("in_tree".into(), strings(self.in_tree.iter().collect()));
+	}
+	let modules: Vec<Value> = self.inputs.iter()
+	    .map(|input| {
+		let mut module = toml::value::Table::new();
+		let (use_stmt, alias) = match input {
+		    Input::File{path, use_stmt, alias} => {
+			module.insert("file".into(), string(path));
+			(use_stmt, alias.as_ref())
+		    },
+		    Input::Glob{pattern, use_stmt, alias} => {
+			module.insert("glob".into(), string(pattern));
+			(use_stmt, alias.as_ref())
+		    },
+		    Input::Layered{layers, pattern, use_stmt} => {
+			module.insert("layers".into(), strings(layers.iter().collect()));
+			module.insert("glob".into(), string(pattern));
+			(use_stmt, None)
+		    },
+		    Input::Crate{use_stmt} => {
+			module.insert("crate".into(), string(use_stmt));
+			(&None, None)
+		    },
+		};
+		if let Some(use_stmt) = use_stmt {
+		    module.insert("use".into(), string(use_stmt));
+		}
+		if let Some(alias) = alias {
+		    module.insert("alias".into(), string(alias));
+		}
+		Value::Table(module)
+	    })
+	    .collect();
+	if !modules.is_empty() {
+	    table.insert("modules".into(), Value::Array(modules));
+	}
+	table.insert("output".into(), Value::Table(self.outputs.iter()
+						  .map(|(kind, path)| (kind.name().to_string(),
+								       string(path)))
+						  .collect()));
+	Value::Table(table)
+    }
+
+    /// Helper function to read the configuration from a TOML table, as `from_toml` does, but
+    /// returning invalid configurations as an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table holding the configuration.
+    fn parse(table: &Value) -> Result<Self, String> {
+	check_keys(table, "configuration", &["visibility", "warn_unused", "env_filter", "target",
+					     "emit_cfgs", "crates", "in_tree", "modules",
+					     "output"])?;
+	let mut spec = Self{
+	    visibility: get_str(table, "visibility")?,
+	    warn_unused: get_bool(table, "warn_unused")?,
+	    env_filter: get_str(table, "env_filter")?,
+	    target: get_str(table, "target")?,
+	    emit_cfgs: get_bool(table, "emit_cfgs")?,
+	    ..Self::default()
+	};
+	if let Some(target) = &spec.target {
+	    if target != "cfg" && target != "filter" {
+		return Err(format!("Invalid target mode '{}'. Expected \"cfg\" or \"filter\".",
+				   target));
+	    }
+	}
+	for module in get_array(table, "modules")? {
+	    check_keys(module, "module", &["file", "glob", "layers", "crate", "use", "alias"])?;
+	    let use_stmt = get_str(module, "use")?;
+	    let alias = get_str(module, "alias")?;
+	    let input = match (get_str(module, "file")?, get_str(module, "glob")?,
+			       module.get("layers"), get_str(module, "crate")?) {
+		(Some(path), None, None, None) => Input::File{path, use_stmt, alias},
+		(None, Some(pattern), None, None) => Input::Glob{pattern, use_stmt, alias},
+		(None, Some(pattern), Some(_), None) if alias.is_none() =>
+		    Input::Layered{layers: get_array(module, "layers")?.iter()
+				   .map(|layer| as_str(layer, "layers"))
+				   .collect::<Result<_, _>>()?,
+				   pattern, use_stmt},
+		(None, None, None, Some(crate_use)) if use_stmt.is_none() && alias.is_none() =>
+		    Input::Crate{use_stmt: crate_use},
+		_ => return Err(format!("Invalid module '{}'. Expected exactly one of `file`, \
+					 `glob` or `crate`, with `layers` only alongside `glob`, \
+					 and `use` or `alias` only alongside `file` or `glob`.",
+					module)),
+	    };
+	    spec.inputs.push(input);
+	}
+	for use_stmt in get_array(table, "crates")? {
+	    spec.inputs.push(Input::Crate{use_stmt: as_str(use_stmt, "crates")?});
+	}
+	spec.in_tree = get_array(table, "in_tree")?.iter()
+	    .map(|path| as_str(path, "in_tree"))
+	    .collect::<Result<_, _>>()?;
+	match table.get("output") {
+	    Some(Value::Table(outputs)) => {
+		for (name, path) in outputs {
+		    match OutputKind::from_name(name) {
+			Some(kind) => spec.outputs.push((kind, as_str(path, name)?)),
+			None => return Err(format!("Invalid output '{}'. Expected one of \
+						    all, mod, use, test, bench, asset or \
+						    manifest.", name)),
+		    }
+		}
+	    },
+	    Some(other) => return Err(format!("Invalid output '{}'. Expected a table.", other)),
+	    None => spec.outputs.push((OutputKind::All, "proc_use.rs".to_string())),
+	}
+	Ok(spec)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Spec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+	self.to_toml().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Spec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+	let table = Value::deserialize(deserializer)?;
+	Self::parse(&table).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The fully resolved plan of what a `UseBuilder` will generate. With the `serde` feature, it
+/// can be dumped in any format serde supports, with the outputs as an `output` table like in
+/// `proc_use.toml`.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// # use proc_use::{OutputKind, Plan, UseBuilder};
+/// let plan = UseBuilder::new()
+///         .use_glob("src/*.rs", "*".into())
+///         .output(OutputKind::All, "proc_use.rs".into())
+///         .plan();
+/// let json = serde_json::to_string(&plan).unwrap();
+/// assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), plan);
+/// let toml = toml::to_string(&plan).unwrap();
+/// assert_eq!(toml::from_str::<Plan>(&toml).unwrap(), plan);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Plan {
+    /// The use statements for crates.
+    pub crates: Vec<String>,
+    /// The files in the source tree written by `write_outputs`, relative to the package.
+    pub in_tree: Vec<String>,
+    /// Every module found, in order.
+    pub modules: Vec<PlannedModule>,
+    /// The files written by `write_outputs`, relative to `OUT_DIR`.
+    #[cfg_attr(feature = "serde", serde(rename = "output", with = "output_table"))]
+    pub outputs: Vec<(OutputKind, String)>,
+}
+
+/// A module in a `Plan`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlannedModule {
+    /// The name of the generated mod.
+    pub name: String,
+    /// The canonical path to the module's source file.
+    pub path: String,
+    /// The attributes placed above the mod and use statements, including `#[cfg]`s.
+    pub attrs: Vec<String>,
+    /// The visibility of the mod and use statements, empty if private.
+    pub visibility: String,
+    /// The use statements for the module.
+    pub uses: Vec<String>,
+    /// The input the module was found by, eg ``glob `src/util/*.rs` ``, if any.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub origin: Option<String>,
+    /// Why the module was left out, if it was.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub excluded: Option<String>,
+}
+
+/// Serializes the outputs of a `Plan` as a table from output kind to path.
+#[cfg(feature = "serde")]
+mod output_table {
+    use super::OutputKind;
+    use serde::de::{Error, MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(outputs: &[(OutputKind, String)], serializer: S)
+				    -> Result<S::Ok, S::Error> {
+	let mut map = serializer.serialize_map(Some(outputs.len()))?;
+	for (kind, path) in outputs {
+	    map.serialize_entry(kind.name(), path)?;
+	}
+	map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+						  -> Result<Vec<(OutputKind, String)>, D::Error> {
+	struct OutputVisitor;
+
+	impl<'de> Visitor<'de> for OutputVisitor {
+	    type Value = Vec<(OutputKind, String)>;
+
+	    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a table from output kind to path")
+	    }
+
+	    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+		let mut outputs = Vec::new();
+		while let Some((name, path)) = map.next_entry::<String, String>()? {
+		    match OutputKind::from_name(&name) {
+			Some(kind) => outputs.push((kind, path)),
+			None => return Err(A::Error::custom(format!(
+			    "Invalid output '{}'. Expected one of \
+			     all, mod, use, test, bench, asset or manifest.", name))),
+		    }
+		}
+		Ok(outputs)
+	    }
+	}
+
+	deserializer.deserialize_map(OutputVisitor)
+    }
+}
+
+/// Helper function to read and parse a TOML file.
+///
+/// # Arguments
+///
+/// * `path` - The file to read.
+fn read_toml(path: &Path) -> Value {
+    let contents = match std::fs::read_to_string(path) {
+	Ok(contents) => contents,
+	Err(err) => panic!("Could not read file '{}': {}", path.to_string_lossy(), err),
+    };
+    match contents.parse() {
+	Ok(value) => value,
+	Err(err) => panic!("Could not parse file '{}': {}", path.to_string_lossy(), err),
+    }
+}
+
+/// Helper function to check a TOML table only holds the keys it may, so a misspelt option is
+/// not silently ignored.
+///
+/// # Arguments
+///
+/// * `table` - The table to check.
+/// * `what` - What the table configures, for error messages.
+/// * `keys` - The keys the table may hold.
+fn check_keys(table: &Value, what: &str, keys: &[&str]) -> Result<(), String> {
+    let unknown = table.as_table().into_iter()
+	.flat_map(|table| table.keys())
+	.find(|key| !keys.contains(&key.as_str()));
+    match unknown {
+	Some(key) => Err(format!("Invalid key `{}` in {}. Expected one of `{}`.",
+				 key, what, keys.join("`, `"))),
+	None => Ok(()),
+    }
+}
+
+/// Helper function to convert a TOML value to a string.
+///
+/// # Arguments
+///
+/// * `value` - The value to convert.
+/// * `key` - The key the value was found under, for error messages.
+fn as_str(value: &Value, key: &str) -> Result<String, String> {
+    match value.as_str() {
+	Some(s) => Ok(s.to_string()),
+	None => Err(format!("Invalid value '{}' for `{}`. Expected a string.", value, key)),
+    }
+}
+
+/// Helper function to read an optional string from a TOML table.
+///
+/// # Arguments
+///
+/// * `table` - The table to read from.
+/// * `key` - The key to read.
+fn get_str(table: &Value, key: &str) -> Result<Option<String>, String> {
+    table.get(key).map(|value| as_str(value, key)).transpose()
+}
+
+/// Helper function to read an optional boolean from a TOML table, defaulting to false.
+///
+/// # Arguments
+///
+/// * `table` - The table to read from.
+/// * `key` - The key to read.
+fn get_bool(table: &Value, key: &str) -> Result<bool, String> {
+    match table.get(key) {
+	Some(value) => match value.as_bool() {
+	    Some(b) => Ok(b),
+	    None => Err(format!("Invalid value '{}' for `{}`. Expected a boolean.", value, key)),
+	},
+	None => Ok(false),
+    }
+}
+
+/// Helper function to read an optional array from a TOML table, defaulting to empty.
+///
+/// # Arguments
+///
+/// * `table` - The table to read from.
+/// * `key` - The key to read.
+fn get_array<'a>(table: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    match table.get(key) {
+	Some(value) => match value.as_array() {
+	    Some(array) => Ok(array),
+	    None => Err(format!("Invalid value '{}' for `{}`. Expected an array.", value, key)),
+	},
+	None => Ok(&[]),
+    }
+}
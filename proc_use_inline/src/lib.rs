@@ -10,268 +10,652 @@
 //! [https://docs.rs/proc_use_inline]: https://docs.rs/proc_use_inline
 
 ////////////////////////////////////////////////////////////////////////////////
+#![cfg_attr(feature = "nightly", feature(proc_macro_tracked_path, proc_macro_diagnostic))]
 extern crate proc_macro;
 
-use syn::{File, parse_macro_input, spanned::Spanned};
-use proc_macro::{Delimiter, TokenStream, TokenTree, Ident};
+use syn::{parse_macro_input, Token};
+use syn::parse::{Parse, ParseStream};
+use proc_macro::TokenStream;
 use quote::quote;
+use std::path::{Path, PathBuf};
 
 fn mk_err<T: quote::ToTokens>(t: T, msg: String) -> syn::Error {
     syn::Error::new_spanned(t, msg)
 }
 
-fn ident_match(term: &str, ident: &syn::Ident) -> syn::Result<bool> {
-    if ident.to_string().as_str() == term {
-	return Ok(true);
-    }
-    
-    Err(syn::Error::new(
-	ident.span(),
-	format!("Error expected ident to say {} and got {}.", term, ident.to_string())
-    ))
+/// The input of `proc_use!`: a list of entries.
+struct Input {
+    entries: Vec<Entry>,
+}
+
+/// An entry of `proc_use!`.
+enum Entry {
+    /// A use item, with its `#[mod]` attribute if it has one. With `#[mod(glob = "...")]`, the
+    /// tree is what follows `*::`, used from every module matched.
+    Use(Option<ModAttr>, Box<syn::ItemUse>),
+    /// A `mod("path");` or `mod(glob = "...");` statement, with the attributes to give the
+    /// modules.
+    Mod(Vec<syn::Attribute>, ModAttr),
+}
+
+/// A `#[mod]`, `#[mod("path", ...)]` or `#[mod(glob = "...")]` attribute, declaring the
+/// modules named by a use, optionally with a visibility first as in `#[mod(pub, "path")]`, and
+/// a name for the module of a single path as in `#[mod("path", as = "name")]`.
+struct ModAttr {
+    /// The `mod` keyword, for error spans.
+    mod_token: Token![mod],
+    /// The visibility of the modules.
+    vis: syn::Visibility,
+    /// The paths to the modules' files, if given.
+    paths: Vec<syn::LitStr>,
+    /// The glob pattern matching the modules' files, relative to `CARGO_MANIFEST_DIR`, if given.
+    glob: Option<syn::LitStr>,
+    /// The name of the module declared from the path, if given.
+    alias: Option<syn::LitStr>,
 }
 
-fn extract_path(attr_str: &str, item: &mut syn::ItemUse) -> syn::Result<Option<String>> {
-    let num_attrs = item.attrs.len();
-    if num_attrs == 1 {
-	let attr = item.attrs.pop().unwrap();
-	let segments = attr.path.segments;
-	let num_segments = segments.len();
-	if num_segments < 1 || num_segments > 1 {
-	    return Err(mk_err(
-		segments,
-		format!("Error: expected 1 segment but recieved {}.", num_segments)
-	    ));
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut entries = Vec::new();
+	while !input.is_empty() {
+	    entries.push(input.parse()?);
 	}
-	return match ident_match(attr_str, &segments[0].ident) {
-	    Ok(true) if !attr.tokens.is_empty() => {
-		match syn::parse2::<syn::ExprParen>(attr.tokens) {
-		    Ok(paren) => {
-			match *paren.expr {
-			    syn::Expr::Lit(syn::ExprLit{lit: syn::Lit::Str(path), attrs: _}) => {
-				Ok(Some(path.value()))
-			    }
-			    err => Err(syn::Error::new(err.span(),
-						       "Expected string literal for path"
-			    )),
-			}
-		    },
-		    Err(err) => Err(err),
+	Ok(Input{entries})
+    }
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut mod_attr = None;
+	let mut attrs = Vec::new();
+	while input.peek(Token![#]) {
+	    let pound_token = input.parse()?;
+	    let content;
+	    let bracket_token = syn::bracketed!(content in input);
+	    if content.peek(Token![mod]) {
+		let attr: ModAttr = content.parse()?;
+		if !content.is_empty() {
+		    return Err(content.error(
+			"Error: expected #[mod], #[mod(\"path\")] or #[mod(glob = \"pattern\")]."
+		    ));
 		}
-	    },
-	    Err(err) => Err(err),
-	    _ => Ok(None),
+		if mod_attr.is_some() {
+		    return Err(mk_err(attr.mod_token,
+				      "Error: expected at most one #[mod] attribute.".to_string()));
+		}
+		mod_attr = Some(attr);
+	    } else {
+		attrs.push(syn::Attribute{pound_token,
+					  style: syn::AttrStyle::Outer,
+					  bracket_token,
+					  path: content.call(syn::Path::parse_mod_style)?,
+					  tokens: content.parse()?});
+	    }
+	}
+
+	if input.peek(Token![mod]) {
+	    let attr: ModAttr = input.parse()?;
+	    if mod_attr.is_some() {
+		return Err(mk_err(attr.mod_token,
+				  "Error: #[mod] is not supported on mod(\"path\").".to_string()));
+	    }
+	    if attr.paths.is_empty() && attr.glob.is_none() {
+		return Err(input.error("Error: expected mod(\"path\") or mod(glob = \"pattern\")."));
+	    }
+	    input.parse::<Token![;]>()?;
+	    check_attrs(&attrs)?;
+	    return Ok(Entry::Mod(attrs, attr));
+	}
+
+	let mut item: syn::ItemUse = if mod_attr.as_ref().is_some_and(|attr| attr.glob.is_some()) {
+	    let vis = input.parse()?;
+	    let use_token = input.parse()?;
+	    if !input.peek(Token![*]) || !input.peek2(Token![::]) {
+		return Err(input.error("Error: expected `use *::...;` after #[mod(glob = ...)]."));
+	    }
+	    input.parse::<Token![*]>()?;
+	    input.parse::<Token![::]>()?;
+	    syn::ItemUse{attrs: Vec::new(), vis, use_token, leading_colon: None,
+			 tree: input.parse()?, semi_token: input.parse()?}
+	} else {
+	    input.parse()?
 	};
-    } else if num_attrs > 1 {
-	return Err(mk_err(
-	    item,
-	    format!("Error: expected 1 attribute but recieved {}.", num_attrs)
-	));
+	if mod_attr.is_some() {
+	    check_attrs(&attrs)?;
+	}
+	item.attrs = attrs;
+	Ok(Entry::Use(mod_attr, Box::new(item)))
     }
-    Ok(None)
 }
 
-fn tree_path(tree: &syn::UseTree) -> String {
-    match tree {
-	syn::UseTree::Path(path) => {
-	    path.ident.to_string()
-	},
-	_ => "err".to_string()
+impl Parse for ModAttr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut attr = ModAttr{mod_token: input.parse()?, vis: syn::Visibility::Inherited,
+			       paths: Vec::new(), glob: None, alias: None};
+	if !input.peek(syn::token::Paren) {
+	    return Ok(attr);
+	}
+	let content;
+	syn::parenthesized!(content in input);
+	while !content.is_empty() {
+	    if content.peek(Token![pub]) || content.peek(Token![crate]) {
+		if attr.vis != syn::Visibility::Inherited || !attr.paths.is_empty()
+		    || attr.glob.is_some() || attr.alias.is_some() {
+			return Err(content.error("Error: expected the visibility first."));
+		    }
+		attr.vis = content.parse()?;
+	    } else if content.peek(Token![as]) && content.peek2(Token![=]) {
+		let as_token: Token![as] = content.parse()?;
+		content.parse::<Token![=]>()?;
+		let value: syn::LitStr = content.parse()?;
+		if attr.alias.is_some() {
+		    return Err(mk_err(as_token, "Error: expected a single alias.".to_string()));
+		}
+		if syn::parse_str::<syn::Ident>(&value.value()).is_err() {
+		    return Err(mk_err(&value, format!("Error: `{}` is not a valid module name.",
+						      value.value())));
+		}
+		attr.alias = Some(value);
+	    } else if content.peek(syn::Ident) && content.peek2(Token![=]) {
+		let key: syn::Ident = content.parse()?;
+		content.parse::<Token![=]>()?;
+		let value: syn::LitStr = content.parse()?;
+		match key.to_string().as_str() {
+		    "glob" if attr.glob.is_none() => attr.glob = Some(value),
+		    "glob" => return Err(mk_err(&key, "Error: expected a single glob.".to_string())),
+		    _ => return Err(mk_err(&key, format!("Error: unknown argument `{}`.", key))),
+		}
+	    } else {
+		attr.paths.push(content.parse()
+				.map_err(|err| syn::Error::new(err.span(),
+							       "Expected string literal for path"))?);
+	    }
+	    if !content.is_empty() {
+		content.parse::<Token![,]>()?;
+	    }
+	}
+	if let (false, Some(glob)) = (attr.paths.is_empty(), &attr.glob) {
+	    return Err(mk_err(glob, "Error: expected paths or a glob, not both.".to_string()));
+	}
+	if let (false, Some(alias)) = (attr.paths.len() == 1, &attr.alias) {
+	    return Err(mk_err(alias,
+			      "Error: an alias can only be given with a single path.".to_string()));
+	}
+	Ok(attr)
+    }
+}
+
+/// The attributes which can be given alongside `#[mod]`, and are forwarded to the modules.
+const MOD_ATTRS: &[&str] = &["cfg", "cfg_attr", "doc", "allow", "macro_use"];
+
+/// Checks the attributes given alongside `#[mod]` can be forwarded to the modules declared.
+fn check_attrs(attrs: &[syn::Attribute]) -> syn::Result<()> {
+    match attrs.iter().find(|attr| !MOD_ATTRS.iter().any(|name| attr.path.is_ident(name))) {
+	Some(attr) => Err(mk_err(
+	    attr,
+	    format!("Error: unsupported attribute alongside #[mod]. Expected one of {}.",
+		    MOD_ATTRS.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", "))
+	)),
+	None => Ok(()),
     }
 }
 
-fn tree_paths(tree: &syn::UseTree) -> Vec<String> {
+/// The attributes of a `#[mod]` use to keep on the use itself: all but `#[macro_use]`, which
+/// only applies to the modules.
+fn use_attrs(attrs: Vec<syn::Attribute>) -> Vec<syn::Attribute> {
+    attrs.into_iter().filter(|attr| !attr.path.is_ident("macro_use")).collect()
+}
+
+/// Finds the modules a `#[mod]` use tree declares: the first segment of each path, looking
+/// through `self::` and groups.
+fn tree_mods(tree: &syn::UseTree) -> syn::Result<Vec<syn::Ident>> {
     match tree {
+	syn::UseTree::Path(path) if path.ident == "self" => tree_mods(&path.tree),
+	syn::UseTree::Path(syn::UsePath{ident, ..})
+	    | syn::UseTree::Name(syn::UseName{ident})
+	    | syn::UseTree::Rename(syn::UseRename{ident, ..}) => {
+		if ident == "self" || ident == "super" || ident == "crate" {
+		    return Err(syn::Error::new(
+			ident.span(),
+			format!("Error: `{}` cannot be declared as a module.", ident)
+		    ));
+		}
+		Ok(vec![ident.clone()])
+	    },
+	syn::UseTree::Glob(glob) => Err(mk_err(
+	    glob,
+	    "Error: cannot find a module name in a glob. Name the module before `*`.".to_string()
+	)),
 	syn::UseTree::Group(group) => {
-	    group.items.iter().map(|item| tree_path(item)).collect()
+	    let mut mods = Vec::new();
+	    for tree in group.items.iter() {
+		mods.extend(tree_mods(tree)?);
+	    }
+	    Ok(mods)
 	},
-	item => vec![tree_path(item)]
     }
 }
 
-fn expand(items: Vec<syn::Item>) -> TokenStream  {
-    let mut mod_stmts = Vec::new();
-    let mut use_stmts = Vec::new();
-    
-    for item_outer in items.into_iter() {
-	match item_outer {
-	    syn::Item::Use(mut item_use) => {
-		let res = extract_path("__mod", &mut item_use);
-		item_use.attrs.clear();
+/// Pairs the modules a `#[mod("path", ...)]` use tree declares with their paths, by file stem
+/// if each module has a path named after it, or else in order. Without paths, every module is
+/// declared without one.
+///
+/// # Arguments
+///
+/// * `tree` - The use tree, for error spans.
+/// * `mods` - The modules the tree declares, without duplicates.
+/// * `paths` - The paths given in `#[mod]`.
+fn pair_paths<'a>(tree: &syn::UseTree, mods: Vec<syn::Ident>, paths: &'a [syn::LitStr])
+		  -> syn::Result<Vec<(syn::Ident, Option<&'a syn::LitStr>)>> {
+    if paths.is_empty() {
+	return Ok(mods.into_iter().map(|ident| (ident, None)).collect());
+    }
+    if paths.len() != mods.len() {
+	return Err(mk_err(tree, format!("Error: expected a path for each module, found {} \
+					 path(s) for {} module(s).", paths.len(), mods.len())));
+    }
+    let by_stem: Option<Vec<&syn::LitStr>> = mods.iter()
+	.map(|ident| paths.iter().find(|path| Path::new(&path.value()).file_stem()
+				       .is_some_and(|stem| *ident == stem.to_string_lossy())))
+	.collect();
+    match by_stem {
+	Some(found) if paths.len() > 1 => Ok(mods.into_iter().zip(found.into_iter().map(Some))
+					      .collect()),
+	_ => Ok(mods.into_iter().zip(paths.iter().map(Some)).collect()),
+    }
+}
 
-		match res {
-		    Ok(None) => {
-			let mod_names = tree_paths(&item_use.tree);
-			for mod_name in mod_names {
-			    let mod_stmt = format!("mod {};", mod_name);
-			    match syn::parse_str::<syn::ItemMod>(&mod_stmt) {
-				Ok(item) => {
-				    mod_stmts.push(item);
-				},
-				Err(err) => {
-				    return TokenStream::from(err.to_compile_error());
-				}
-			    }
-			}
-		    },
-		    Ok(Some(path)) => {
-			let mod_name = tree_path(&item_use.tree); // only one mod with a path decl
-			let mod_stmt = format!("mod {};", mod_name);
-			match syn::parse_str::<syn::ItemMod>(&mod_stmt) {
-			    Ok(mut item) => {
-				item.attrs.push(
-				    syn::parse2::<syn::ItemStruct>(quote!{
-					#[path = #path]
-					struct Dummy;
-				    }).unwrap().attrs.pop().unwrap());
-				mod_stmts.push(item);
-			    },
-			    Err(err) => {
-				return TokenStream::from(err.to_compile_error());   
-			    }
-			}
-		    },
-		    Err(err) => return TokenStream::from(err.to_compile_error())
-		}
-		
-		use_stmts.push(item_use);
-	    },
-	    syn::Item::Const(syn::ItemConst{attrs, vis, const_token: _, ident, colon_token: _, ty,
-					    eq_token: _, expr, semi_token: _}) if
-		attrs.len() == 0 && vis == syn::Visibility::Inherited && ident.to_string() == "r#mod"
-		=> { // de-sugared mod()
-		    match (*ty, *expr) {
-			(syn::Type::Infer(syn::TypeInfer{underscore_token: syn::token::Underscore{spans: _}}),
-			 syn::Expr::Lit(syn::ExprLit{attrs, lit: syn::Lit::Str(lit)}))
-			    if attrs.len() == 0 => {
-				let path_str = lit.value();
-				let path_buf = std::path::PathBuf::from(path_str.clone());
-				match (path_buf.file_stem(), path_buf.extension()) { // ensure file is valid
-				    (Some(mod_name), Some(ext)) if ext == "rs" => {
-					match mod_name.to_str() {
-					    Some(mod_str) => {
-						let mod_stmt = format!("mod {};", mod_str);
-						match syn::parse_str::<syn::ItemMod>(&mod_stmt) {
-						    Ok(mut item) => {
-							item.attrs.push(
-							    syn::parse2::<syn::ItemStruct>(quote!{
-								#[path = #path_str]
-								struct Dummy;
-							    }).unwrap().attrs.pop().unwrap());
-							mod_stmts.push(item);
-						    },
-						    Err(err) => {
-							return TokenStream::from(err.to_compile_error());
-						    }
-						}
-					    },
-					    None => return TokenStream::from(mk_err(
-						lit,
-						"Invalid file. Only UTF8 file names are valie".to_string()
-					    ).to_compile_error()),
-					}
-				    },
-				    (Some(_), _) => return TokenStream::from(mk_err(
-					lit,
-					"Invalid file. Possible causes: file is not a Rust file.".to_string()
-				    ).to_compile_error()),
-				    (None, _) => return TokenStream::from(mk_err(
-					lit,
-					"Invalid file. Possible causes: does not exist, is not a regular file.".to_string()
-				    ).to_compile_error()),
-				}
-			    },
-			_ => return TokenStream::from(mk_err(
-			    ident,
-			    "Error: found const r#mod without proper syntax. Likely an internal error.".to_string()
-			).to_compile_error()),
-		    }
-		},
-	    err => {
-		return TokenStream::from(mk_err(
-		    err,
-		    "Error: Expected syn::ItemUse. More info found at https://docs.rs/syn/1.0.30/syn/struct.ItemUse.html.".to_string()
-		).to_compile_error());
+/// Removes bare module names from a `#[mod]` use tree, as `mod foo;` already brings `foo`
+/// into scope and `use foo;` would clash with it. Returns `None` if nothing is left to use.
+fn prune_tree(tree: syn::UseTree) -> Option<syn::UseTree> {
+    match tree {
+	syn::UseTree::Path(mut path) if path.ident == "self" => {
+	    let tree = prune_tree(*path.tree)?;
+	    path.tree = Box::new(tree);
+	    Some(syn::UseTree::Path(path))
+	},
+	syn::UseTree::Name(_) => None,
+	syn::UseTree::Group(mut group) => {
+	    group.items = group.items.into_iter().filter_map(prune_tree).collect();
+	    if group.items.is_empty() {
+		None
+	    } else {
+		Some(syn::UseTree::Group(group))
 	    }
+	},
+	tree => Some(tree),
+    }
+}
+
+/// Finds the module a file declares, named after its stem.
+///
+/// # Arguments
+///
+/// * `path_buf` - The path to the file.
+/// * `lit` - The literal the path came from, for error spans.
+fn stem_mod(path_buf: &Path, lit: &syn::LitStr) -> syn::Result<syn::Ident> {
+    match (path_buf.file_stem(), path_buf.extension()) { // ensure file is valid
+	(Some(mod_name), Some(ext)) if ext == "rs" => {
+	    match mod_name.to_str() {
+		Some(mod_str) => syn::parse_str::<syn::Ident>(mod_str)
+		    .map(|ident| syn::Ident::new(&ident.to_string(), lit.span()))
+		    .map_err(|_| mk_err(
+			lit,
+			format!("Invalid file '{}'. `{}` is not a valid module name.",
+				path_buf.to_string_lossy(), mod_str)
+		    )),
+		None => Err(mk_err(
+		    lit,
+		    "Invalid file. Only UTF8 file names are valie".to_string()
+		)),
+	    }
+	},
+	(Some(_), _) => Err(mk_err(
+	    lit,
+	    "Invalid file. Possible causes: file is not a Rust file.".to_string()
+	)),
+	(None, _) => Err(mk_err(
+	    lit,
+	    "Invalid file. Possible causes: does not exist, is not a regular file.".to_string()
+	)),
+    }
+}
+
+/// Finds the file a module's path refers to, relative to the directory of the file invoking
+/// the macro as for `#[path]`, returning it as a literal for `include_bytes!`. Needs the
+/// `nightly` feature, as `Span::local_file` is only stable from Rust 1.88.
+///
+/// A path which is not found is left for rustc to report, as inside an inline `mod { ... }`
+/// block rustc looks in a subdirectory instead. With a nightly compiler, the closest Rust files
+/// are also suggested, unless the module has a `#[cfg]` and may not need the file, or the path
+/// is found from a subdirectory.
+///
+/// # Arguments
+///
+/// * `attrs` - The attributes given to the module.
+/// * `lit` - The path.
+#[cfg(feature = "nightly")]
+fn resolve_path(attrs: &[syn::Attribute], lit: &syn::LitStr) -> Option<syn::LitStr> {
+    let file = proc_macro::Span::call_site().local_file()?; // not invoked from a file on disk
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    let path = dir.join(lit.value());
+    if path.is_file() {
+	let found = path.canonicalize().unwrap_or(path);
+	return Some(syn::LitStr::new(&found.to_string_lossy(), lit.span()));
+    }
+    let nested = Path::new(&glob::Pattern::escape(&dir.to_string_lossy())).join("**")
+	.join(glob::Pattern::escape(&lit.value()));
+    let in_subdir = glob::glob(&nested.to_string_lossy())
+	.is_ok_and(|mut paths| paths.any(|path| path.is_ok()));
+    if in_subdir || attrs.iter().any(|attr| attr.path.is_ident("cfg")) {
+	return None; // may be found from an inline module, or not needed
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut candidates: Vec<(usize, String)> = path.parent()
+	.and_then(|dir| std::fs::read_dir(dir).ok())
+	.into_iter()
+	.flatten()
+	.filter_map(|entry| entry.ok())
+	.map(|entry| entry.file_name().to_string_lossy().to_string())
+	.filter(|candidate| candidate.ends_with(".rs"))
+	.map(|candidate| (edit_distance(&name, &candidate), candidate))
+	.filter(|(distance, _)| *distance <= std::cmp::max(name.len() / 3, 2))
+	.collect();
+    candidates.sort();
+    let suggestions: Vec<String> = candidates.into_iter()
+	.take(3)
+	.map(|(_, candidate)| format!("\"{}\"",
+				      Path::new(&lit.value()).with_file_name(candidate)
+				      .to_string_lossy()))
+	.collect();
+    let msg = match suggestions.len() {
+	0 => return None,
+	1 => suggestions[0].clone(),
+	n => format!("{} or {}", suggestions[..n - 1].join(", "), suggestions[n - 1]),
+    };
+    lit.span().unwrap()
+	.help(format!("\"{}\" was not found next to this file. Did you mean {}?",
+		      lit.value(), msg))
+	.emit();
+    None
+}
+
+/// Without the `nightly` feature, paths are left for rustc to find.
+#[cfg(not(feature = "nightly"))]
+fn resolve_path(_attrs: &[syn::Attribute], _lit: &syn::LitStr) -> Option<syn::LitStr> {
+    None
+}
+
+/// The Levenshtein distance between two strings.
+#[cfg(feature = "nightly")]
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+	let mut prev = row[0];
+	row[0] = i + 1;
+	for (j, cb) in b.iter().enumerate() {
+	    let next = std::cmp::min(std::cmp::min(row[j + 1], row[j]) + 1,
+				     prev + if ca == *cb { 0 } else { 1 });
+	    prev = row[j + 1];
+	    row[j + 1] = next;
 	}
     }
+    row[b.len()]
+}
 
-    TokenStream::from(quote! {
-	#(#mod_stmts)*
-	#(#use_stmts)*
-    })
+/// Finds the files matched by a glob pattern relative to `CARGO_MANIFEST_DIR`, sorted, with
+/// the module each declares.
+///
+/// # Arguments
+///
+/// * `lit` - The glob pattern.
+fn glob_mods(lit: &syn::LitStr) -> syn::Result<Vec<(syn::Ident, String)>> {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let pattern = dir.join(lit.value());
+    #[cfg(feature = "nightly")] // rebuild when files are added to or removed from the directory
+    proc_macro::tracked::path(glob_dir(&pattern).to_string_lossy().to_string());
+    let paths = glob::glob(&pattern.to_string_lossy())
+	.map_err(|err| mk_err(lit, format!("Invalid glob pattern: {}", err)))?;
+    let mut paths: Vec<PathBuf> = paths.filter_map(Result::ok).collect();
+    paths.sort();
+    paths.into_iter()
+	.map(|path| Ok((stem_mod(&path, lit)?, path.to_string_lossy().to_string())))
+	.collect()
+}
+
+/// Finds the directory a glob pattern searches, ie the longest leading path without any glob
+/// metacharacters.
+#[cfg(feature = "nightly")]
+fn glob_dir(pattern: &Path) -> PathBuf {
+    pattern.components()
+	.take_while(|component| !component.as_os_str().to_string_lossy()
+		    .contains(['*', '?', '[']))
+	.collect()
+}
+
+/// A module declared by `proc_use!`.
+struct Declared {
+    /// The attributes given alongside `#[mod]`.
+    attrs: Vec<syn::Attribute>,
+    /// The visibility of the module.
+    vis: syn::Visibility,
+    /// The name of the module.
+    ident: syn::Ident,
+    /// The path to the module's file as given, if any.
+    path: Option<syn::LitStr>,
+    /// The file the path was found at, to rebuild on.
+    file: Option<syn::LitStr>,
 }
 
-// 1) replaces keyword `mod` with `__mod` in attribute contexts
-// 2) replaces `mod($PATH)` with `const r#mod: _ = $PATH;`
-fn desugar(input: TokenStream) -> TokenStream {
-    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
-    for i in 0..tokens.len()-1 {
-	match &tokens[i] {
-	    TokenTree::Punct(p) if p.as_char() == '#' => {
-		match &tokens[i+1] {
-		    TokenTree::Group(g) if g.delimiter() == Delimiter::Bracket => {
-			let mut stream: Vec<TokenTree> = g.stream().into_iter().collect();
-			if stream.len() >= 1 && stream[0].to_string() == "mod" { // this optional arg
-			    stream[0] = TokenTree::Ident(Ident::new("__mod", stream[0].span()));
-			}
-			tokens[i+1] = TokenTree::Group(proc_macro::Group::new(Delimiter::Bracket, stream.into_iter().collect()));
-		    },
-		    TokenTree::Punct(p) if p.as_char() == '!' => {
-			match &tokens[i+2] {
-			    TokenTree::Group(g) if g.delimiter() == Delimiter::Bracket => {
-				let mut stream: Vec<TokenTree> = g.stream().into_iter().collect();
-				if stream.len() == 1 && stream[0].to_string() == "mod" { // this does not
-				    stream[0] = TokenTree::Ident(Ident::new("__mod", stream[0].span()));
-				}
-				tokens[i+2] = TokenTree::Group(proc_macro::Group::new(Delimiter::Bracket, stream.into_iter().collect()));
-			    },
-			    _ => {},
-			}
-		    },
-		    _ => {},
+/// Adds a module to those declared, unless it already is. The same module declared again with
+/// another path or visibility is an error, spanning both declarations. Declarations with
+/// different attributes, such as exclusive `#[cfg]`s, are kept apart.
+///
+/// # Arguments
+///
+/// * `declared` - The modules declared so far.
+/// * `module` - The module to declare.
+fn declare(declared: &mut Vec<Declared>, module: Declared) -> syn::Result<()> {
+    let attrs = |module: &Declared| module.attrs.iter()
+	.map(|attr| quote!(#attr).to_string())
+	.collect::<Vec<_>>();
+    let prev = match declared.iter()
+	.find(|prev| prev.ident == module.ident && attrs(prev) == attrs(&module)) {
+	    Some(prev) => prev,
+	    None => {
+		declared.push(module);
+		return Ok(());
+	    },
+	};
+    let file = |module: &Declared| module.file.as_ref().or(module.path.as_ref())
+	.map(syn::LitStr::value);
+    let what = if file(prev) != file(&module) {
+	"paths"
+    } else if prev.vis != module.vis {
+	"visibilities"
+    } else {
+	return Ok(());
+    };
+    let span = |module: &Declared| module.path.as_ref()
+	.map_or(module.ident.span(), syn::LitStr::span);
+    let mut err = syn::Error::new(span(&module), format!("Error: conflicting {} for module `{}`.",
+							   what, module.ident));
+    err.combine(syn::Error::new(span(prev), format!("Error: module `{}` is first declared here.",
+						     module.ident)));
+    Err(err)
+}
+
+/// Builds the mod item declaring a module, from a file if a path is given.
+///
+/// # Arguments
+///
+/// * `attrs` - The attributes given alongside `#[mod]`.
+/// * `vis` - The visibility of the module.
+/// * `ident` - The name of the module.
+/// * `path` - The path to the module's file, if given.
+fn mod_item(attrs: &[syn::Attribute], vis: &syn::Visibility, ident: &syn::Ident,
+	    path: Option<&syn::LitStr>) -> syn::ItemMod {
+    match path {
+	Some(path) => syn::parse_quote!{
+	    #(#attrs)*
+	    #[path = #path]
+	    #vis mod #ident;
+	},
+	None => syn::parse_quote!{
+	    #(#attrs)*
+	    #vis mod #ident;
+	},
+    }
+}
+
+fn expand(input: Input) -> syn::Result<proc_macro2::TokenStream> {
+    let mut declared: Vec<Declared> = Vec::new();
+    let mut use_stmts = Vec::new();
+
+    for entry in input.entries.into_iter() {
+	match entry {
+	    Entry::Use(None, item_use) => use_stmts.push(*item_use), // plain use, passed through
+	    Entry::Use(Some(ModAttr{vis, glob: Some(glob), ..}), item_use) => {
+		for (ident, path) in glob_mods(&glob)? {
+		    let path = syn::LitStr::new(&path, glob.span());
+		    declare(&mut declared, Declared{attrs: item_use.attrs.clone(), vis: vis.clone(),
+						    ident: ident.clone(), path: Some(path.clone()),
+						    file: Some(path)})?;
+		    let mut item_use = item_use.clone();
+		    item_use.attrs = use_attrs(item_use.attrs);
+		    item_use.tree = syn::UseTree::Path(syn::UsePath{
+			ident,
+			colon2_token: Default::default(),
+			tree: Box::new(item_use.tree),
+		    });
+		    use_stmts.push(*item_use);
 		}
 	    },
-	    TokenTree::Ident(__mod) if __mod.to_string() == "mod" => {
-		match &tokens[i+1] {
-		    TokenTree::Group(g) if g.delimiter() == Delimiter::Parenthesis => {
-			let stream: Vec<TokenTree> = g.stream().into_iter().collect();
-			if stream.len() == 1  {
-			    let path_quoted = stream[0].to_string();
-			    if path_quoted.as_bytes()[0] == '"' as u8 && path_quoted.chars().last() == Some('"') {
-				let path = &path_quoted[1..path_quoted.len()-1];
-				let new_tokens: Vec<TokenTree> = TokenStream::from(quote!{
-				    const r#mod: _ = #path
-				}).into_iter().collect();
-				tokens.splice(i..i+2, new_tokens.into_iter());
-			    }
-			} else {
-			    tokens[i+1] = TokenTree::Group(proc_macro::Group::new(Delimiter::Bracket, stream.into_iter().collect()));
-			}
-		    },
-		    _ => {},
+	    Entry::Use(Some(mod_attr), mut item_use) => {
+		let mut mods = tree_mods(&item_use.tree)?;
+		let mut seen = Vec::new();
+		mods.retain(|ident| if seen.contains(ident) {
+		    false
+		} else {
+		    seen.push(ident.clone());
+		    true
+		});
+		let mods = pair_paths(&item_use.tree, mods, &mod_attr.paths)?;
+		if let Some(alias) = &mod_attr.alias {
+		    if mods[0].0 != alias.value() {
+			return Err(mk_err(
+			    &item_use.tree,
+			    format!("Error: expected the use to name the module `{}`.", alias.value())
+			));
+		    }
 		}
-	    }
-	    _ => {},
+		for (ident, path) in mods {
+		    let file = match path {
+			Some(path) => resolve_path(&item_use.attrs, path),
+			None => None,
+		    };
+		    declare(&mut declared, Declared{attrs: item_use.attrs.clone(),
+						    vis: mod_attr.vis.clone(), ident,
+						    path: path.cloned(), file})?;
+		}
+		if let Some(tree) = prune_tree(item_use.tree.clone()) {
+		    item_use.tree = tree;
+		    item_use.attrs = use_attrs(item_use.attrs);
+		    use_stmts.push(*item_use);
+		}
+	    },
+	    Entry::Mod(attrs, ModAttr{vis, glob: Some(glob), ..}) => {
+		for (ident, path) in glob_mods(&glob)? {
+		    let path = syn::LitStr::new(&path, glob.span());
+		    declare(&mut declared, Declared{attrs: attrs.clone(), vis: vis.clone(), ident,
+						    path: Some(path.clone()), file: Some(path)})?;
+		}
+	    },
+	    Entry::Mod(attrs, ModAttr{vis, paths, alias, ..}) => {
+		for path in paths {
+		    let ident = match &alias {
+			Some(alias) => syn::Ident::new(&alias.value(), alias.span()),
+			None => stem_mod(Path::new(&path.value()), &path)?,
+		    };
+		    let file = resolve_path(&attrs, &path);
+		    declare(&mut declared, Declared{attrs: attrs.clone(), vis: vis.clone(), ident,
+						    path: Some(path), file})?;
+		}
+	    },
 	}
     }
-    
-    tokens.into_iter().collect()
+
+    let mod_stmts = declared.iter()
+	.map(|module| mod_item(&module.attrs, &module.vis, &module.ident, module.path.as_ref()));
+    let deps = declared.iter().filter_map(|module| module.file.as_ref()); // via include_bytes!
+    Ok(quote! {
+	#(#mod_stmts)*
+	#(#use_stmts)*
+	#(const _: &[u8] = include_bytes!(#deps);)*
+    })
 }
 
-/// proc_use! macro, takes mod and use syntax to generate mod and use statements.
+/// proc_use! macro, takes mod and use syntax to generate mod and use statements. A use
+/// without `#[mod]` is passed through untouched, so crate imports can share the block.
+///
+/// `#[mod]` declares the first segment of every path in the use, including within groups, so
+/// `#[mod] use {a::*, b::{c, d}};` declares `a` and `b`. `#[mod] use foo;` just declares `foo`,
+/// and `#[mod] use foo as bar;` declares `foo` and imports it as `bar`.
+///
+/// The visibility of a use applies to the use, so `pub use` re-exports. The modules' own
+/// visibility is given first in `#[mod]`, as in `#[mod(pub)]`, `#[mod(pub(crate), "path.rs")]`
+/// or `mod(pub, glob = "src/util/*.rs");`.
+///
+/// The input is a list of:
+/// * `use ...;`, passed through.
+/// * `#[mod] use ...;`, declaring the modules named by the use.
+/// * `#[mod("path.rs")] use ...;`, declaring the module named by the use from a file.
+/// * `#[mod("a.rs", "b.rs")] use {a::*, b::*};`, declaring the modules named by the use from
+///   files, matched by stem when each module has a file named after it, or else in order.
+/// * `mod("path.rs");`, declaring a module named after the file, or with several paths, a
+///   module for each.
+/// * `mod("path.rs", as = "name");`, declaring a module with another name from a file. The
+///   same form, `#[mod("path.rs", as = "name")]`, checks a use names the module `name`.
+/// * `#[mod(glob = "src/util/*.rs")] use *::...;`, declaring a module for each file matched,
+///   named after the file, and using `...` from each. Patterns are relative to the package
+///   directory, and matches are sorted.
+/// * `mod(glob = "src/util/*.rs");`, declaring a module for each file matched.
+///
+/// `#[cfg]`, `#[cfg_attr]`, `#[doc]`, `#[allow]` and `#[macro_use]` can be given alongside
+/// `#[mod]` or before `mod(...);`, and are forwarded to the modules declared as well as the use,
+/// so `#[cfg(unix)] #[mod] use unix::*;` only declares and uses `unix` on unix. `#[macro_use]`
+/// is only given to the modules.
+///
+/// A module named more than once, as in `#[mod] use foo::a; #[mod] use foo::b;`, is declared
+/// once. Naming it with different paths or visibilities is an error.
+///
+/// Paths are relative to the invoking file's directory, as for `#[path]`, and a missing file is
+/// reported by rustc. With the `nightly` feature, the closest existing files are suggested too.
+///
+/// Every file globbed is also included as a hidden `const _: &[u8]`, so edits to it rebuild
+/// the crate, as rustc does for files given by path. Rebuilding when a file is added to a
+/// globbed directory needs the `nightly` feature, which tracks the directory on nightly
+/// compilers.
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// proc_use_inline::proc_use! {
 ///    #[mod]
 ///    use foo::*;
 ///    #[mod("../external/bar.rs")]
 ///    use bar::bar;
+///    use std::collections::HashMap;
+///    mod("../external/baz.rs");
+///    mod("../external/qux.rs", as = "aliased");
+///    #[mod(glob = "src/plugins/*.rs")]
+///    use *::run;
 /// }
 /// ```
 #[proc_macro]
 pub fn proc_use(input: TokenStream) -> TokenStream {
-    let input = desugar(input);
-    expand(parse_macro_input!(input as File).items)
+    match expand(parse_macro_input!(input as Input)) {
+	Ok(tokens) => TokenStream::from(tokens),
+	Err(err) => TokenStream::from(err.to_compile_error()),
+    }
 }
@@ -0,0 +1,3 @@
+proc_use_inline::proc_use! {}
+
+fn main() {}
@@ -0,0 +1,21 @@
+// The examples/inline crate, with its files given relative to this one.
+proc_use_inline::proc_use! {
+    #[mod("../../../examples/inline/src/foo.rs")]
+    use foo::*;
+    #[mod("../../../examples/inline/external/bar.rs")]
+    use bar::bar;
+    mod("../../../examples/inline/external/qux.rs", as = "aliased");
+    use std::collections::HashMap;
+    mod("../../../examples/inline/src/plugins/alpha.rs",
+	"../../../examples/inline/src/plugins/beta.rs");
+}
+
+fn main() {
+    foo();
+    bar();
+    aliased::qux();
+    let mut counts = HashMap::new();
+    counts.insert("foo", 1);
+    alpha::run();
+    beta::run();
+}
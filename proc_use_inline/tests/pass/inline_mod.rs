@@ -0,0 +1,11 @@
+// Inside an inline module, paths are relative to its subdirectory, as rustc finds them.
+mod sub {
+    proc_use_inline::proc_use! {
+	#[mod("inner.rs")]
+	pub use inner::x;
+    }
+}
+
+fn main() {
+    sub::x();
+}
@@ -0,0 +1,6 @@
+proc_use_inline::proc_use! {
+    #[mod("fixtures/module.rs", as = "renamed")]
+    use module::*;
+}
+
+fn main() {}
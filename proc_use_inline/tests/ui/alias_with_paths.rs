@@ -0,0 +1,5 @@
+proc_use_inline::proc_use! {
+    mod("fixtures/module.rs", "fixtures/other.rs", as = "both");
+}
+
+fn main() {}
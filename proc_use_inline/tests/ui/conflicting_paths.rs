@@ -0,0 +1,8 @@
+proc_use_inline::proc_use! {
+    #[mod("fixtures/module.rs")]
+    use module::f;
+    #[mod("fixtures/other/module.rs")]
+    use module::g;
+}
+
+fn main() {}
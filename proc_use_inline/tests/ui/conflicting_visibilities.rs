@@ -0,0 +1,8 @@
+proc_use_inline::proc_use! {
+    #[mod(pub)]
+    use module::f;
+    #[mod]
+    use module::g;
+}
+
+fn main() {}
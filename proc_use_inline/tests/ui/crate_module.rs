@@ -0,0 +1,6 @@
+proc_use_inline::proc_use! {
+    #[mod]
+    use crate::module;
+}
+
+fn main() {}
@@ -0,0 +1,5 @@
+proc_use_inline::proc_use! {
+    mod();
+}
+
+fn main() {}
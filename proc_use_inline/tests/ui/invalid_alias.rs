@@ -0,0 +1,5 @@
+proc_use_inline::proc_use! {
+    mod("fixtures/module.rs", as = "not-a-module");
+}
+
+fn main() {}
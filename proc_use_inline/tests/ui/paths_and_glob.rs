@@ -0,0 +1,5 @@
+proc_use_inline::proc_use! {
+    mod("fixtures/module.rs", glob = "tests/ui/*.rs");
+}
+
+fn main() {}
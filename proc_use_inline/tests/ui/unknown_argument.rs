@@ -0,0 +1,5 @@
+proc_use_inline::proc_use! {
+    mod(path = "fixtures/module.rs");
+}
+
+fn main() {}
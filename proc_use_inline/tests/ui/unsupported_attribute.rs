@@ -0,0 +1,7 @@
+proc_use_inline::proc_use! {
+    #[inline]
+    #[mod]
+    use module::*;
+}
+
+fn main() {}
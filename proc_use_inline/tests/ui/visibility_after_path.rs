@@ -0,0 +1,6 @@
+proc_use_inline::proc_use! {
+    #[mod("fixtures/module.rs", pub)]
+    use module::f;
+}
+
+fn main() {}